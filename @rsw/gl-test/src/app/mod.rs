@@ -1,11 +1,24 @@
 use web_sys::WebGlRenderingContext;
+use crate::input::{InputEvent, InputState};
 use crate::render::Renderer;
+use crate::render::error::RenderError;
 use wasm_bindgen::JsValue;
 
 pub trait Application {
     fn start(&mut self) -> Result<(), JsValue>;
-    fn update(&mut self, dt: f32);
+    fn update(&mut self, dt: f32, input: &InputState);
+    /// React to a single input event as it arrives. Most apps only need the `InputState` passed to `update`.
+    fn on_input(&mut self, _event: &InputEvent) {}
     fn render(&self);
     fn exit(&self);
+    /// Recompile one of the application's shaders in place from new sources, keeping the old program on failure.
+    /// Only used while developing, apps that don't support it fail with `RenderError::Unsupported`.
+    fn reload_shader(&mut self, _name: &str, _vertex: &str, _fragment: &str) -> Result<(), JsValue> {
+        Err(RenderError::Unsupported(String::from("shader hot-reload")).into())
+    }
+    /// Replace a chunk shaders `#include` and recompile every shader from its current sources.
+    fn reload_shader_chunk(&mut self, _name: &str, _source: &str) -> Result<(), JsValue> {
+        Err(RenderError::Unsupported(String::from("shader hot-reload")).into())
+    }
     fn get_renderer(&self) -> &dyn Renderer;
 }
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use nalgebra::Vector2;
+use web_sys::{HtmlCanvasElement, KeyboardEvent, MouseEvent, TouchEvent, WheelEvent};
+use crate::render::camera::screen_to_ndc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Other(i16),
+}
+
+impl MouseButton {
+    /// Map the DOM `MouseEvent.button` index.
+    pub fn from_dom(button: i16) -> MouseButton {
+        match button {
+            0 => MouseButton::Left,
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            other => MouseButton::Other(other),
+        }
+    }
+}
+
+/// Input translated out of the DOM. Positions are in normalized device coordinates
+/// of the canvas, so `(-1, -1)` is the bottom left corner and `(1, 1)` the top right.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    PointerMove { position: Vector2<f32>, delta: Vector2<f32> },
+    PointerDown { position: Vector2<f32>, button: MouseButton },
+    PointerUp { position: Vector2<f32>, button: MouseButton },
+    /// Positive deltas scroll down, roughly one unit per notch of a mouse wheel.
+    Wheel { delta: Vector2<f32> },
+    /// `code` is the physical key, e.g. `"KeyW"`, so bindings survive keyboard layouts.
+    KeyDown { code: String, key: String, repeat: bool },
+    KeyUp { code: String, key: String },
+    TouchStart { id: i32, position: Vector2<f32> },
+    TouchMove { id: i32, position: Vector2<f32> },
+    TouchEnd { id: i32, position: Vector2<f32> },
+}
+
+impl InputEvent {
+    pub fn pointer_move(event: &MouseEvent, canvas: &HtmlCanvasElement) -> InputEvent {
+        let rect = canvas.get_bounding_client_rect();
+        let delta = Vector2::new(
+            event.movement_x() as f32 / rect.width() as f32 * 2.0,
+            -event.movement_y() as f32 / rect.height() as f32 * 2.0,
+        );
+        InputEvent::PointerMove { position: client_to_ndc(event.client_x(), event.client_y(), canvas), delta }
+    }
+
+    pub fn pointer_down(event: &MouseEvent, canvas: &HtmlCanvasElement) -> InputEvent {
+        InputEvent::PointerDown {
+            position: client_to_ndc(event.client_x(), event.client_y(), canvas),
+            button: MouseButton::from_dom(event.button()),
+        }
+    }
+
+    pub fn pointer_up(event: &MouseEvent, canvas: &HtmlCanvasElement) -> InputEvent {
+        InputEvent::PointerUp {
+            position: client_to_ndc(event.client_x(), event.client_y(), canvas),
+            button: MouseButton::from_dom(event.button()),
+        }
+    }
+
+    pub fn wheel(event: &WheelEvent) -> InputEvent {
+        // Browsers report pixels, lines or pages depending on the device.
+        let scale = match event.delta_mode() {
+            WheelEvent::DOM_DELTA_PIXEL => 0.01,
+            WheelEvent::DOM_DELTA_LINE => 1.0 / 3.0,
+            _ => 1.0,
+        };
+        InputEvent::Wheel { delta: Vector2::new(event.delta_x() as f32, event.delta_y() as f32) * scale }
+    }
+
+    pub fn key_down(event: &KeyboardEvent) -> InputEvent {
+        InputEvent::KeyDown { code: event.code(), key: event.key(), repeat: event.repeat() }
+    }
+
+    pub fn key_up(event: &KeyboardEvent) -> InputEvent {
+        InputEvent::KeyUp { code: event.code(), key: event.key() }
+    }
+
+    /// One event per touch that changed, built with `make` from the touch id and position.
+    pub fn touches<F>(event: &TouchEvent, canvas: &HtmlCanvasElement, make: F) -> Vec<InputEvent>
+        where F: Fn(i32, Vector2<f32>) -> InputEvent {
+        let touches = event.changed_touches();
+        (0..touches.length())
+            .filter_map(|i| touches.get(i))
+            .map(|t| make(t.identifier(), client_to_ndc(t.client_x(), t.client_y(), canvas)))
+            .collect()
+    }
+}
+
+fn client_to_ndc(x: i32, y: i32, canvas: &HtmlCanvasElement) -> Vector2<f32> {
+    let rect = canvas.get_bounding_client_rect();
+    screen_to_ndc(
+        (x as f64 - rect.left()) as f32,
+        (y as f64 - rect.top()) as f32,
+        rect.width() as f32,
+        rect.height() as f32,
+    )
+}
+
+/// The current state of every input, for applications that would rather poll than react
+/// to events. Pointer and wheel deltas add up until `end_frame` is called.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    keys: HashSet<String>,
+    buttons: HashSet<MouseButton>,
+    touches: HashMap<i32, Vector2<f32>>,
+    pub pointer: Vector2<f32>,
+    pub pointer_delta: Vector2<f32>,
+    pub wheel: Vector2<f32>,
+}
+
+impl InputState {
+    pub fn new() -> InputState {
+        InputState::default()
+    }
+
+    pub fn handle(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::PointerMove { position, delta } => {
+                self.pointer = *position;
+                self.pointer_delta += delta;
+            }
+            InputEvent::PointerDown { position, button } => {
+                self.pointer = *position;
+                self.buttons.insert(*button);
+            }
+            InputEvent::PointerUp { position, button } => {
+                self.pointer = *position;
+                self.buttons.remove(button);
+            }
+            InputEvent::Wheel { delta } => self.wheel += delta,
+            InputEvent::KeyDown { code, .. } => { self.keys.insert(code.clone()); }
+            InputEvent::KeyUp { code, .. } => { self.keys.remove(code); }
+            InputEvent::TouchStart { id, position } | InputEvent::TouchMove { id, position } => {
+                self.touches.insert(*id, *position);
+            }
+            InputEvent::TouchEnd { id, .. } => { self.touches.remove(id); }
+        }
+    }
+
+    /// Reset the per frame deltas, called once every update.
+    pub fn end_frame(&mut self) {
+        self.pointer_delta = Vector2::zeros();
+        self.wheel = Vector2::zeros();
+    }
+
+    /// Forget everything held down, such as when the page loses focus and release events never arrive.
+    pub fn release_all(&mut self) {
+        self.keys.clear();
+        self.buttons.clear();
+        self.touches.clear();
+    }
+
+    /// Whether a key is held, by its physical `code` such as `"KeyW"` or `"ArrowUp"`.
+    pub fn is_key_down(&self, code: &str) -> bool {
+        self.keys.contains(code)
+    }
+
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    pub fn get_touches(&self) -> &HashMap<i32, Vector2<f32>> {
+        &self.touches
+    }
+
+    /// -1, 0 or 1 depending on which of two opposing keys are held.
+    pub fn axis(&self, negative: &str, positive: &str) -> f32 {
+        (self.is_key_down(positive) as i32 - self.is_key_down(negative) as i32) as f32
+    }
+}
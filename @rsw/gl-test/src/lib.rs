@@ -5,30 +5,45 @@ use wasm_bindgen::JsCast;
 use web_sys::WebGlRenderingContext;
 
 mod app;
+mod input;
 mod render;
+mod scene;
 mod shader;
 
 use shader::Shader;
 use shader::{SHADER_SIMPLE_FRAG, SHADER_SIMPLE_VERT};
 use app::*;
+use input::{InputEvent, InputState};
 use render::*;
 use render::mesh::Mesh;
+use render::resource::ResourceCounts;
 use nalgebra::vector;
 
 mod test_app;
 use test_app::TestApplication;
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
 }
 
+/// Outside of the browser there is no console to bind to. Tests log to stderr, which the harness
+/// only shows for failing tests, while other native builds stay quiet.
+#[cfg(not(target_arch = "wasm32"))]
+fn log(_s: &str) {
+    #[cfg(test)]
+    eprintln!("{}", _s);
+}
+
 macro_rules! console_log { ($($t:tt)*) => (log(&format_args!($($t)*).to_string())) }
 
 #[wasm_bindgen]
 pub struct WebClient {
     app: TestApplication,
+    canvas: web_sys::HtmlCanvasElement,
+    input: InputState,
 }
 
 #[wasm_bindgen]
@@ -38,8 +53,8 @@ impl WebClient {
         let document = web_sys::window().unwrap().document().unwrap();
         let canvas = document.get_element_by_id("canvas").unwrap();
         let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
-        let app = TestApplication::new(canvas).unwrap();
-        Ok(WebClient { app })
+        let app = TestApplication::new(canvas.clone())?;
+        Ok(WebClient { app, canvas, input: InputState::new() })
     }
 
     pub fn start(&mut self) -> Result<(), JsValue> {
@@ -48,7 +63,8 @@ impl WebClient {
     }
 
     pub fn update(&mut self, dt: f32) {
-        self.app.update(dt);
+        self.app.update(dt, &self.input);
+        self.input.end_frame();
     }
 
     pub fn render(&self) {
@@ -63,4 +79,84 @@ impl WebClient {
     pub fn exit(&self) {
         self.app.exit();
     }
+
+    pub fn pointer_move(&mut self, event: &web_sys::MouseEvent) {
+        self.dispatch(InputEvent::pointer_move(event, &self.canvas));
+    }
+
+    pub fn pointer_down(&mut self, event: &web_sys::MouseEvent) {
+        self.dispatch(InputEvent::pointer_down(event, &self.canvas));
+    }
+
+    pub fn pointer_up(&mut self, event: &web_sys::MouseEvent) {
+        self.dispatch(InputEvent::pointer_up(event, &self.canvas));
+    }
+
+    pub fn wheel(&mut self, event: &web_sys::WheelEvent) {
+        self.dispatch(InputEvent::wheel(event));
+    }
+
+    pub fn key_down(&mut self, event: &web_sys::KeyboardEvent) {
+        self.dispatch(InputEvent::key_down(event));
+    }
+
+    pub fn key_up(&mut self, event: &web_sys::KeyboardEvent) {
+        self.dispatch(InputEvent::key_up(event));
+    }
+
+    pub fn touch_start(&mut self, event: &web_sys::TouchEvent) {
+        for e in InputEvent::touches(event, &self.canvas, |id, position| InputEvent::TouchStart { id, position }) {
+            self.dispatch(e);
+        }
+    }
+
+    pub fn touch_move(&mut self, event: &web_sys::TouchEvent) {
+        for e in InputEvent::touches(event, &self.canvas, |id, position| InputEvent::TouchMove { id, position }) {
+            self.dispatch(e);
+        }
+    }
+
+    /// Forward both `touchend` and `touchcancel` here.
+    pub fn touch_end(&mut self, event: &web_sys::TouchEvent) {
+        for e in InputEvent::touches(event, &self.canvas, |id, position| InputEvent::TouchEnd { id, position }) {
+            self.dispatch(e);
+        }
+    }
+
+    /// Recompile one of the application's shaders, such as `simple` or `flatcolor`, without rebuilding the wasm.
+    /// The old program keeps being used if the new sources fail to compile.
+    pub fn reload_shader(&mut self, name: &str, vert: &str, frag: &str) -> Result<(), JsValue> {
+        self.app.reload_shader(name, vert, frag)
+    }
+
+    /// Replace a chunk shaders `#include`, such as `lighting`, and recompile every shader using the new version.
+    pub fn reload_shader_chunk(&mut self, name: &str, source: &str) -> Result<(), JsValue> {
+        self.app.reload_shader_chunk(name, source)
+    }
+
+    /// How many GPU objects are alive, to spot leaks by watching it across frames.
+    pub fn gpu_resources(&self) -> String {
+        ResourceCounts::current().to_string()
+    }
+
+    /// Time uploading `verticies` vertex positions, averaged over `iterations` uploads,
+    /// one float at a time against in bulk. Only built with the `bench` feature.
+    #[cfg(feature = "bench")]
+    pub fn benchmark_mesh_upload(&self, verticies: u32, iterations: u32) -> Result<String, JsValue> {
+        let benchmark = render::mesh::bench::benchmark_upload(self.app.get_renderer(), verticies as usize, iterations)?;
+        Ok(benchmark.to_string())
+    }
+
+    /// Call when the page loses focus, as the matching key and button releases will never arrive.
+    pub fn blur(&mut self) {
+        self.input.release_all();
+    }
+}
+
+impl WebClient {
+    fn dispatch(&mut self, event: InputEvent) {
+        self.input.handle(&event);
+        self.app.on_input(&event);
+    }
 }
+
@@ -0,0 +1,352 @@
+use nalgebra::{ Vector2, Vector3, Vector4, Matrix4, Point3 };
+use crate::input::{InputEvent, InputState, MouseButton};
+use crate::render::Renderer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov` is the vertical field of view in degrees.
+    Perspective { fov: f32, near: f32, far: f32 },
+    /// `height` is how much of the world fits vertically on screen.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.direction * t
+    }
+
+    /// Where the ray crosses a plane, if it does so in front of its origin.
+    pub fn intersect_plane(&self, point: Vector3<f32>, normal: Vector3<f32>) -> Option<Vector3<f32>> {
+        let denom = self.direction.dot(&normal);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let t = (point - self.origin).dot(&normal) / denom;
+        if t < 0.0 {
+            return None;
+        }
+
+        Some(self.at(t))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+    /// World to camera transform. When the camera is attached to a scene node the node's
+    /// world transform is used instead.
+    pub view: Matrix4<f32>,
+    pub aspect: f32,
+    /// Follow the renderer's aspect ratio rather than `aspect`.
+    pub auto_aspect: bool,
+}
+
+impl Camera {
+    pub fn perspective(fov: f32, near: f32, far: f32) -> Camera {
+        Camera {
+            projection: Projection::Perspective { fov, near, far },
+            view: Matrix4::identity(),
+            aspect: 1.0,
+            auto_aspect: true,
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Camera {
+        Camera {
+            projection: Projection::Orthographic { height, near, far },
+            view: Matrix4::identity(),
+            aspect: 1.0,
+            auto_aspect: true,
+        }
+    }
+
+    /// Pick up the renderer's aspect ratio, call this whenever the canvas may have been resized.
+    pub fn update_aspect(&mut self, render: &dyn Renderer) {
+        if self.auto_aspect {
+            self.aspect = render.aspect();
+        }
+    }
+
+    pub fn look_at(&mut self, eye: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) {
+        self.view = Matrix4::look_at_rh(&Point3::from(eye), &Point3::from(target), &up);
+    }
+
+    /// Where the camera is in world space, taken from the inverse of the view matrix.
+    pub fn position(&self) -> Vector3<f32> {
+        self.view.try_inverse().map(|m| m.column(3).xyz()).unwrap_or_else(Vector3::zeros)
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        self.view
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_with_aspect(self.aspect)
+    }
+
+    /// The projection matrix using the renderer's current aspect ratio when `auto_aspect` is set.
+    pub fn projection_for(&self, render: &dyn Renderer) -> Matrix4<f32> {
+        if self.auto_aspect {
+            self.projection_with_aspect(render.aspect())
+        } else {
+            self.projection_matrix()
+        }
+    }
+
+    fn projection_with_aspect(&self, aspect: f32) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective { fov, near, far } => {
+                Matrix4::new_perspective(aspect, fov.to_radians(), near, far)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let (w, h) = (height * aspect * 0.5, height * 0.5);
+                Matrix4::new_orthographic(-w, w, -h, h, near, far)
+            }
+        }
+    }
+
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view
+    }
+
+    /// Take a point in normalized device coordinates back into world space.
+    /// `None` if the view projection can't be undone, such as when the view matrix is degenerate.
+    pub fn unproject(&self, ndc: Vector3<f32>) -> Option<Vector3<f32>> {
+        let vp = self.view_projection();
+        let vp_inv = vp.try_inverse().or_else(|| vp.pseudo_inverse(0.000001).ok())?;
+        let world: Vector4<f32> = vp_inv * ndc.push(1.0);
+        Some(world.xyz() / world.w).filter(|p| p.iter().all(|c| c.is_finite()))
+    }
+
+    /// The ray through a point on screen in normalized device coordinates,
+    /// starting on the near plane and heading into the scene.
+    pub fn ray_from_screen(&self, ndc: Vector2<f32>) -> Option<Ray> {
+        let near = self.unproject(ndc.push(-1.0))?;
+        let far = self.unproject(ndc.push(1.0))?;
+        Some(Ray { origin: near, direction: (far - near).try_normalize(f32::EPSILON)? })
+    }
+}
+
+/// Convert a position in canvas pixels, with y pointing down, into normalized device coordinates.
+pub fn screen_to_ndc(x: f32, y: f32, width: f32, height: f32) -> Vector2<f32> {
+    Vector2::new((x / width) * 2.0 - 1.0, 1.0 - (y / height) * 2.0)
+}
+
+/// Something that moves a camera around, typically in response to user input.
+pub trait CameraController {
+    fn apply(&self, camera: &mut Camera);
+    /// React to a single input event, such as a drag or a wheel step.
+    fn handle_input(&mut self, event: &InputEvent);
+    /// Advance using inputs that are held down over time, such as movement keys.
+    fn update(&mut self, input: &InputState, dt: f32);
+}
+
+/// Circles around a target point, always looking at it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitController {
+    pub target: Vector3<f32>,
+    pub distance: f32,
+    /// Rotation around the vertical axis in radians.
+    pub yaw: f32,
+    /// Rotation above the horizon in radians.
+    pub pitch: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Radians turned per unit of pointer movement.
+    pub sensitivity: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: Vector3<f32>, distance: f32) -> OrbitController {
+        OrbitController {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            min_distance: 0.1,
+            max_distance: 100.0,
+            sensitivity: 1.0,
+        }
+    }
+
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.001;
+        self.yaw -= dx * self.sensitivity;
+        self.pitch = (self.pitch + dy * self.sensitivity).clamp(-limit, limit);
+    }
+
+    /// Move towards the target for positive amounts, scaled by the current distance.
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance = (self.distance * (1.0 - amount)).clamp(self.min_distance, self.max_distance);
+    }
+
+    pub fn eye(&self) -> Vector3<f32> {
+        let offset = Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        self.target + offset * self.distance
+    }
+}
+
+impl CameraController for OrbitController {
+    fn apply(&self, camera: &mut Camera) {
+        camera.look_at(self.eye(), self.target, Vector3::y());
+    }
+
+    fn handle_input(&mut self, event: &InputEvent) {
+        if let InputEvent::Wheel { delta } = event {
+            self.zoom(-delta.y * 0.1);
+        }
+    }
+
+    /// Dragging with the left button held rotates around the target.
+    fn update(&mut self, input: &InputState, _dt: f32) {
+        if input.is_button_down(MouseButton::Left) {
+            self.rotate(input.pointer_delta.x, input.pointer_delta.y);
+        }
+    }
+}
+
+/// Free first person movement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlyController {
+    pub position: Vector3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// World units moved per second.
+    pub speed: f32,
+    /// Radians turned per unit of pointer movement.
+    pub sensitivity: f32,
+}
+
+impl FlyController {
+    pub fn new(position: Vector3<f32>) -> FlyController {
+        FlyController { position, yaw: 0.0, pitch: 0.0, speed: 1.0, sensitivity: 1.0 }
+    }
+
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.001;
+        self.yaw -= dx * self.sensitivity;
+        self.pitch = (self.pitch + dy * self.sensitivity).clamp(-limit, limit);
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(
+            -self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    /// Move relative to where the camera faces, each axis in the range `[-1, 1]`.
+    pub fn travel(&mut self, forward: f32, right: f32, up: f32, dt: f32) {
+        let fwd = self.forward();
+        let side = fwd.cross(&Vector3::y()).normalize();
+        self.position += (fwd * forward + side * right + Vector3::y() * up) * self.speed * dt;
+    }
+}
+
+impl CameraController for FlyController {
+    fn apply(&self, camera: &mut Camera) {
+        camera.look_at(self.position, self.position + self.forward(), Vector3::y());
+    }
+
+    fn handle_input(&mut self, _event: &InputEvent) {}
+
+    /// WASD to move, space and shift to rise and sink, and drag with the left button to look around.
+    fn update(&mut self, input: &InputState, dt: f32) {
+        if input.is_button_down(MouseButton::Left) {
+            self.look(input.pointer_delta.x, input.pointer_delta.y);
+        }
+
+        self.travel(
+            input.axis("KeyS", "KeyW"),
+            input.axis("KeyA", "KeyD"),
+            input.axis("ShiftLeft", "Space"),
+            dt,
+        );
+    }
+}
+
+/// Slides and scales a flat view, for orthographic cameras looking down the z axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanZoomController {
+    pub center: Vector2<f32>,
+    /// Magnification, larger values show less of the world.
+    pub zoom: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// World units panned per unit of pointer movement at a zoom of one.
+    pub pan_speed: f32,
+}
+
+impl PanZoomController {
+    pub fn new(center: Vector2<f32>) -> PanZoomController {
+        PanZoomController { center, zoom: 1.0, min_zoom: 0.01, max_zoom: 100.0, pan_speed: 1.0 }
+    }
+
+    /// Move the view by an offset in world units at the current zoom.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.center -= Vector2::new(dx, dy) / self.zoom;
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(self.min_zoom, self.max_zoom);
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn apply(&self, camera: &mut Camera) {
+        let scale = Vector3::new(self.zoom, self.zoom, 1.0);
+        camera.view = Matrix4::new_nonuniform_scaling(&scale) * Matrix4::new_translation(&(-self.center).push(0.0));
+    }
+
+    fn handle_input(&mut self, event: &InputEvent) {
+        if let InputEvent::Wheel { delta } = event {
+            self.zoom_by(1.1f32.powf(-delta.y));
+        }
+    }
+
+    /// Dragging with the left button held slides the view along with the pointer.
+    fn update(&mut self, input: &InputState, _dt: f32) {
+        if input.is_button_down(MouseButton::Left) {
+            let delta = input.pointer_delta * self.pan_speed;
+            self.pan(delta.x, delta.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+
+    #[test]
+    fn ray_through_center_hits_target() {
+        let mut camera = Camera::perspective(60.0, 0.1, 100.0);
+        camera.look_at(vector!(0.0, 0.0, 5.0), Vector3::zeros(), Vector3::y());
+
+        let ray = camera.ray_from_screen(Vector2::zeros()).unwrap();
+        assert!((ray.origin - vector!(0.0, 0.0, 4.9)).norm() < 1e-3);
+        assert!((ray.direction - vector!(0.0, 0.0, -1.0)).norm() < 1e-4);
+        let hit = ray.intersect_plane(Vector3::zeros(), Vector3::z()).unwrap();
+        assert!(hit.norm() < 1e-3);
+    }
+
+    #[test]
+    fn degenerate_view_has_no_ray() {
+        let mut camera = Camera::perspective(60.0, 0.1, 100.0);
+        camera.view = Matrix4::zeros();
+        assert_eq!(camera.unproject(Vector3::zeros()), None);
+        assert_eq!(camera.ray_from_screen(Vector2::zeros()), None);
+    }
+}
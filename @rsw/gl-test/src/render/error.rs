@@ -0,0 +1,140 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+use crate::shader::preprocess::ShaderSource;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+/// Why the renderer or a shader could not do what was asked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderError {
+    /// No WebGL context could be created, or it has been lost.
+    ContextUnavailable(String),
+    /// A shader stage failed to compile. `line` is the first line the log complains about,
+    /// counted within `file`, the shader or included chunk it came from, and `source` the text of that line.
+    ShaderCompile {
+        stage: ShaderStage,
+        log: String,
+        file: Option<String>,
+        line: Option<u32>,
+        source: Option<String>,
+    },
+    ShaderLink(String),
+    BufferAllocation(String),
+    Texture(String),
+    RenderTarget(String),
+    /// The shader has no active uniform by this name, it may have been optimised out.
+    MissingUniform(String),
+    MissingAttribute(String),
+    /// A uniform was set with a value of another type than it is declared with.
+    UniformType {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// Needs WebGL 2, an extension, or a backend that isn't available.
+    Unsupported(String),
+    /// A `LightSet` already holds as many lights as the shader loops over, which is the limit carried.
+    TooManyLights(usize),
+}
+
+impl RenderError {
+    /// A compile error for preprocessed `source`, picking the offending line out of the log
+    /// and mapping it back to the file it came from.
+    pub fn compile(stage: ShaderStage, log: String, source: &ShaderSource) -> RenderError {
+        let line = log.lines().find_map(error_line);
+        let log = log.lines().map(|entry| map_log_line(entry, source)).collect::<Vec<_>>().join("\n");
+        let text = line.and_then(|line| source.get_code().lines().nth(line.checked_sub(1)? as usize)).map(|s| s.trim().to_string());
+        let (file, line) = match line.and_then(|line| source.origin(line)) {
+            Some((file, line)) => (Some(file.to_string()), Some(line)),
+            // Lines the preprocessor generated have no file to point at
+            None => (None, line),
+        };
+        RenderError::ShaderCompile { stage, log, file, line, source: text }
+    }
+
+    /// A compile error at a known line, such as one found while preprocessing.
+    pub fn compile_at(stage: ShaderStage, log: String, file: &str, line: u32, source: &str) -> RenderError {
+        RenderError::ShaderCompile {
+            stage,
+            log,
+            file: Some(file.to_string()),
+            line: Some(line),
+            source: Some(source.trim().to_string()),
+        }
+    }
+
+    /// Describe an error thrown by a web API.
+    pub(crate) fn js_message(err: &JsValue) -> String {
+        err.as_string().unwrap_or_else(|| format!("{:?}", err))
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::ContextUnavailable(reason) => write!(f, "WebGL context unavailable: {}", reason),
+            RenderError::ShaderCompile { stage, log, file, line, source } => {
+                write!(f, "Failed to compile {} shader", stage)?;
+                match (file, line) {
+                    (Some(file), Some(line)) => write!(f, " at {}:{}", file, line)?,
+                    (None, Some(line)) => write!(f, " at line {}", line)?,
+                    _ => {}
+                }
+                if let Some(source) = source {
+                    write!(f, " `{}`", source)?;
+                }
+                write!(f, ": {}", log.trim())
+            }
+            RenderError::ShaderLink(log) => write!(f, "Failed to link shader program: {}", log.trim()),
+            RenderError::BufferAllocation(what) => write!(f, "Unable to allocate {}", what),
+            RenderError::Texture(reason) => write!(f, "Texture error: {}", reason),
+            RenderError::RenderTarget(reason) => write!(f, "Render target error: {}", reason),
+            RenderError::MissingUniform(name) => write!(f, "Shader has no active uniform `{}`", name),
+            RenderError::MissingAttribute(name) => write!(f, "Shader has no active attribute `{}`", name),
+            RenderError::UniformType { name, expected, found } => write!(f, "Uniform `{}` is declared as {} but was set with {}", name, expected, found),
+            RenderError::Unsupported(what) => write!(f, "Unsupported: {}", what),
+            RenderError::TooManyLights(max) => write!(f, "Unable to add a light, the set is limited to {}", max),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<RenderError> for JsValue {
+    fn from(err: RenderError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+/// The line number in a GLSL info log entry such as `ERROR: 0:12: 'x' : undeclared identifier`.
+fn error_line(entry: &str) -> Option<u32> {
+    let rest = entry.trim().strip_prefix("ERROR:")?;
+    let mut parts = rest.split(':');
+    parts.next()?.trim().parse::<u32>().ok()?;
+    parts.next()?.trim().parse().ok()
+}
+
+/// Point a log entry at the file and line it came from rather than the preprocessed output.
+fn map_log_line(entry: &str, source: &ShaderSource) -> String {
+    let origin = error_line(entry).and_then(|line| Some((line, source.origin(line)?)));
+    match origin {
+        Some((line, (file, original))) => {
+            let tail = entry.split_once(&format!(":{}:", line)).map(|(_, tail)| tail).unwrap_or("");
+            format!("ERROR: {}:{}:{}", file, original, tail)
+        }
+        None => entry.to_string(),
+    }
+}
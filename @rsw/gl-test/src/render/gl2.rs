@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use js_sys::Float32Array;
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlVertexArrayObject};
+use crate::render::error::RenderError;
+use crate::render::resource::{ResourceKind, created, deleted};
+
+/// Which WebGL version a `GlRenderer` ended up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlBackend {
+    WebGl1,
+    WebGl2,
+}
+
+/// A vertex array object recording how a mesh's buffers feed a shader's attributes.
+/// It stays valid for as long as the shader's attribute locations and the mesh's layout don't change.
+#[derive(Debug)]
+pub struct VertexArray {
+    vao: WebGlVertexArrayObject,
+    gl: WebGl2RenderingContext,
+    locations: Rc<HashMap<String, u32>>,
+}
+
+impl VertexArray {
+    pub(crate) fn new(gl: &WebGl2RenderingContext, locations: Rc<HashMap<String, u32>>) -> Result<VertexArray, ()> {
+        let vao = gl.create_vertex_array().ok_or(())?;
+        created(ResourceKind::VertexArray);
+        Ok(VertexArray { vao, gl: gl.clone(), locations })
+    }
+
+    /// Whether this was recorded against the same attribute locations.
+    pub(crate) fn matches(&self, locations: &Rc<HashMap<String, u32>>) -> bool {
+        Rc::ptr_eq(&self.locations, locations) || self.locations == *locations
+    }
+
+    pub fn bind(&self) {
+        self.gl.bind_vertex_array(Some(&self.vao));
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        self.gl.delete_vertex_array(Some(&self.vao));
+        deleted(ResourceKind::VertexArray);
+    }
+}
+
+/// A buffer backing a uniform block, shared by every shader bound to the same binding point.
+/// Contents must follow the std140 layout of the block they are read through.
+pub struct UniformBuffer {
+    buffer: WebGlBuffer,
+    gl: WebGl2RenderingContext,
+    size: i32,
+}
+
+impl UniformBuffer {
+    /// Allocate a buffer of `size` bytes.
+    pub fn new(gl: &WebGl2RenderingContext, size: i32) -> Result<UniformBuffer, RenderError> {
+        let buffer = gl.create_buffer().ok_or_else(|| RenderError::BufferAllocation(String::from("uniform buffer")))?;
+        created(ResourceKind::Buffer);
+        gl.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&buffer));
+        gl.buffer_data_with_i32(WebGl2RenderingContext::UNIFORM_BUFFER, size, WebGl2RenderingContext::DYNAMIC_DRAW);
+        Ok(UniformBuffer { buffer, gl: gl.clone(), size })
+    }
+
+    pub fn get_size(&self) -> i32 { self.size }
+
+    /// Replace the contents starting at the beginning of the buffer.
+    pub fn update(&self, data: &[f32]) {
+        self.gl.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&self.buffer));
+        self.gl.buffer_sub_data_with_i32_and_array_buffer_view(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            0,
+            &Float32Array::from(data),
+        );
+    }
+
+    /// Attach to a binding point, see `Shader::bind_uniform_block`.
+    pub fn bind(&self, binding: u32) {
+        self.gl.bind_buffer_base(WebGl2RenderingContext::UNIFORM_BUFFER, binding, Some(&self.buffer));
+    }
+}
+
+impl Drop for UniformBuffer {
+    fn drop(&mut self) {
+        self.gl.delete_buffer(Some(&self.buffer));
+        deleted(ResourceKind::Buffer);
+    }
+}
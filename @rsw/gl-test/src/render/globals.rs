@@ -0,0 +1,62 @@
+use nalgebra::{ Vector2, Vector3, Matrix4 };
+use crate::render::camera::Camera;
+use crate::shader::Shader;
+
+/// Values every shader may want that only change once a frame. The renderer uploads them to each
+/// shader it binds that declares them as `time`, `view`, `projection`, `cameraPosition` and `resolution`.
+/// Thanks to the shader's value cache, binding the same shader again later in the frame costs nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobalUniforms {
+    /// Seconds since the application started.
+    pub time: f32,
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    /// Where the camera is in world space.
+    pub camera_position: Vector3<f32>,
+    /// Size of the canvas in pixels. Post effects replace it with the size of the target they read.
+    pub resolution: Vector2<f32>,
+}
+
+impl GlobalUniforms {
+    pub fn new() -> GlobalUniforms {
+        GlobalUniforms {
+            time: 0.0,
+            view: Matrix4::identity(),
+            projection: Matrix4::identity(),
+            camera_position: Vector3::zeros(),
+            resolution: Vector2::new(1.0, 1.0),
+        }
+    }
+
+    /// Take the view, projection and position from a camera.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.view = camera.view_matrix();
+        self.projection = camera.projection_matrix();
+        self.camera_position = camera.position();
+    }
+
+    /// Upload each value the shader declares, assuming it is bound.
+    pub fn apply(&self, shader: &Shader) {
+        if shader.has_uniform("time") {
+            shader.set_uniform1f("time", self.time).ok();
+        }
+        if shader.has_uniform("view") {
+            shader.set_uniform_matrix4f("view", self.view).ok();
+        }
+        if shader.has_uniform("projection") {
+            shader.set_uniform_matrix4f("projection", self.projection).ok();
+        }
+        if shader.has_uniform("cameraPosition") {
+            shader.set_uniform3f("cameraPosition", self.camera_position).ok();
+        }
+        if shader.has_uniform("resolution") {
+            shader.set_uniform2f("resolution", self.resolution).ok();
+        }
+    }
+}
+
+impl Default for GlobalUniforms {
+    fn default() -> Self {
+        GlobalUniforms::new()
+    }
+}
@@ -0,0 +1,129 @@
+use nalgebra::{ Vector4, Matrix4 };
+use web_sys::{WebGlBuffer, WebGlRenderingContext};
+use crate::render::resource::{BufferUsage, GpuBuffer};
+use crate::render::Renderer;
+use crate::render::layout::{AttributeType, VertexAttribute, VertexLayout};
+
+/// Per instance values for `Renderer::draw_mesh_instanced`, packed one instance after another.
+/// Every attribute is made of floats. Matrix attributes take 9 or 16 components and are fed
+/// to the shader as that many columns of 3 or 4, as GLSL expects for `mat3` and `mat4` attributes.
+pub struct InstanceBuffer {
+    layout: VertexLayout,
+    data: Vec<f32>,
+    buffer: Option<GpuBuffer>,
+}
+
+impl InstanceBuffer {
+    /// Instances with the given float attributes, interleaved in the order they were added.
+    pub fn new(layout: &VertexLayout) -> InstanceBuffer {
+        let mut floats = VertexLayout::new();
+        for attribute in layout.get_attributes() {
+            floats.add(&attribute.name, AttributeType::Float, attribute.components, false);
+        }
+
+        InstanceBuffer { layout: floats.interleaved(), data: Vec::new(), buffer: None }
+    }
+
+    /// An `instanceModel` matrix and `instanceColor`, as read by `instanced.v.glsl`.
+    pub fn standard() -> InstanceBuffer {
+        let mut layout = VertexLayout::new();
+        layout.add("instanceModel", AttributeType::Float, 16, false);
+        layout.add("instanceColor", AttributeType::Float, 4, false);
+        InstanceBuffer::new(&layout)
+    }
+
+    /// Add an instance, `values` being every attribute one after another.
+    /// Missing values are filled with zero and extra values dropped.
+    pub fn push(&mut self, values: &[f32]) {
+        let floats = self.floats_per_instance();
+        self.data.extend(values.iter().copied().chain(std::iter::repeat(0.0)).take(floats));
+    }
+
+    /// Add an instance to a buffer created with `standard`.
+    pub fn push_instance(&mut self, model: Matrix4<f32>, color: Vector4<f32>) {
+        let values: Vec<f32> = model.iter().chain(color.iter()).copied().collect();
+        self.push(&values);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() / self.floats_per_instance().max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn floats_per_instance(&self) -> usize {
+        (self.layout.vertex_size(0) / 4) as usize
+    }
+
+    /// The values of one attribute for one instance.
+    pub fn get_value(&self, instance: usize, name: &str) -> Option<&[f32]> {
+        let attribute = self.layout.get(name)?;
+        let start = instance * self.floats_per_instance() + (attribute.offset / 4) as usize;
+        self.data.get(start..start + attribute.components as usize)
+    }
+
+    pub fn get_layout(&self) -> &VertexLayout { &self.layout }
+    pub fn get_data(&self) -> &Vec<f32> { &self.data }
+    pub fn get_buffer(&self) -> Option<&WebGlBuffer> { self.buffer.as_deref() }
+
+    pub fn update_buffer(&mut self, render: &dyn Renderer) {
+        // Renderers without GL buffers fail to create one and read the instance data directly
+        if self.buffer.is_none() {
+            self.buffer = render.create_buffer().ok();
+        }
+
+        // Instances are expected to change often, the storage is only reallocated when it grows
+        if let Some(buffer) = self.buffer.as_mut() {
+            buffer.reserve(WebGlRenderingContext::ARRAY_BUFFER, (self.data.len() * 4) as i32, BufferUsage::Dynamic);
+            buffer.write_floats(WebGlRenderingContext::ARRAY_BUFFER, 0, &self.data);
+        }
+    }
+}
+
+/// The attribute slots an instance attribute is fed through, as `(location offset, components, byte offset)`.
+/// Matrices take one slot per column.
+pub(crate) fn attribute_columns(attribute: &VertexAttribute) -> Vec<(u32, i32, i32)> {
+    let (columns, size) = match attribute.components {
+        16 => (4, 4),
+        9 => (3, 3),
+        n => (1, n),
+    };
+
+    (0..columns).map(|c| (c as u32, size, attribute.offset + c * size * 4)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+
+    #[test]
+    fn push_pads_and_truncates() {
+        let mut instances = InstanceBuffer::standard();
+        assert_eq!(instances.floats_per_instance(), 20);
+
+        instances.push(&[1.0, 2.0]);
+        instances.push(&[3.0; 25]);
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances.get_data().len(), 40);
+        assert_eq!(instances.get_value(0, "instanceModel").unwrap()[..3], [1.0, 2.0, 0.0]);
+        assert_eq!(instances.get_value(0, "instanceColor"), Some(&[0.0; 4][..]));
+        assert_eq!(instances.get_value(1, "instanceColor"), Some(&[3.0; 4][..]));
+        assert_eq!(instances.get_value(2, "instanceColor"), None);
+    }
+
+    #[test]
+    fn push_instance_reads_back() {
+        let mut instances = InstanceBuffer::standard();
+        let model = Matrix4::new_translation(&vector!(1.0, 2.0, 3.0));
+        instances.push_instance(model, vector!(0.5, 0.25, 1.0, 1.0));
+        assert_eq!(instances.get_value(0, "instanceModel"), Some(model.as_slice()));
+        assert_eq!(instances.get_value(0, "instanceColor"), Some(&[0.5, 0.25, 1.0, 1.0][..]));
+    }
+}
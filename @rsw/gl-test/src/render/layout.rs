@@ -0,0 +1,229 @@
+use web_sys::WebGlRenderingContext;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeType {
+    Float,
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+}
+
+impl AttributeType {
+    pub fn gl_enum(&self) -> u32 {
+        match self {
+            AttributeType::Float => WebGlRenderingContext::FLOAT,
+            AttributeType::Byte => WebGlRenderingContext::BYTE,
+            AttributeType::UnsignedByte => WebGlRenderingContext::UNSIGNED_BYTE,
+            AttributeType::Short => WebGlRenderingContext::SHORT,
+            AttributeType::UnsignedShort => WebGlRenderingContext::UNSIGNED_SHORT,
+        }
+    }
+
+    /// Size of a single component in bytes.
+    pub fn size(&self) -> i32 {
+        match self {
+            AttributeType::Float => 4,
+            AttributeType::Byte | AttributeType::UnsignedByte => 1,
+            AttributeType::Short | AttributeType::UnsignedShort => 2,
+        }
+    }
+}
+
+/// Per vertex values for an attribute, `components` values after one another for each vertex.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeData {
+    Float(Vec<f32>),
+    Byte(Vec<i8>),
+    UnsignedByte(Vec<u8>),
+    Short(Vec<i16>),
+    UnsignedShort(Vec<u16>),
+}
+
+impl AttributeData {
+    pub fn attribute_type(&self) -> AttributeType {
+        match self {
+            AttributeData::Float(_) => AttributeType::Float,
+            AttributeData::Byte(_) => AttributeType::Byte,
+            AttributeData::UnsignedByte(_) => AttributeType::UnsignedByte,
+            AttributeData::Short(_) => AttributeType::Short,
+            AttributeData::UnsignedShort(_) => AttributeType::UnsignedShort,
+        }
+    }
+
+    /// Number of values, not vertices.
+    pub fn len(&self) -> usize {
+        match self {
+            AttributeData::Float(data) => data.len(),
+            AttributeData::Byte(data) => data.len(),
+            AttributeData::UnsignedByte(data) => data.len(),
+            AttributeData::Short(data) => data.len(),
+            AttributeData::UnsignedShort(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy `count` values starting at `start` into `out` as little endian bytes, the byte order WebGL reads.
+    /// Values past the end of the data are written as zero.
+    pub fn write_bytes(&self, start: usize, count: usize, out: &mut [u8]) {
+        let size = self.attribute_type().size() as usize;
+        for i in 0..count {
+            let dst = &mut out[i * size..(i + 1) * size];
+            match self {
+                AttributeData::Float(data) => dst.copy_from_slice(&data.get(start + i).copied().unwrap_or(0.0).to_le_bytes()),
+                AttributeData::Byte(data) => dst.copy_from_slice(&data.get(start + i).copied().unwrap_or(0).to_le_bytes()),
+                AttributeData::UnsignedByte(data) => dst.copy_from_slice(&data.get(start + i).copied().unwrap_or(0).to_le_bytes()),
+                AttributeData::Short(data) => dst.copy_from_slice(&data.get(start + i).copied().unwrap_or(0).to_le_bytes()),
+                AttributeData::UnsignedShort(data) => dst.copy_from_slice(&data.get(start + i).copied().unwrap_or(0).to_le_bytes()),
+            }
+        }
+    }
+
+    /// The values of the given verticies, in order, for an attribute with `components` values per vertex.
+    pub fn gather(&self, verticies: &[usize], components: usize) -> AttributeData {
+        match self {
+            AttributeData::Float(data) => AttributeData::Float(gather_slice(data, verticies, components)),
+            AttributeData::Byte(data) => AttributeData::Byte(gather_slice(data, verticies, components)),
+            AttributeData::UnsignedByte(data) => AttributeData::UnsignedByte(gather_slice(data, verticies, components)),
+            AttributeData::Short(data) => AttributeData::Short(gather_slice(data, verticies, components)),
+            AttributeData::UnsignedShort(data) => AttributeData::UnsignedShort(gather_slice(data, verticies, components)),
+        }
+    }
+
+    /// Add the values of `other` to the end, ignored if the types differ.
+    pub fn append(&mut self, other: AttributeData) {
+        match (self, other) {
+            (AttributeData::Float(data), AttributeData::Float(other)) => data.extend(other),
+            (AttributeData::Byte(data), AttributeData::Byte(other)) => data.extend(other),
+            (AttributeData::UnsignedByte(data), AttributeData::UnsignedByte(other)) => data.extend(other),
+            (AttributeData::Short(data), AttributeData::Short(other)) => data.extend(other),
+            (AttributeData::UnsignedShort(data), AttributeData::UnsignedShort(other)) => data.extend(other),
+            _ => {}
+        }
+    }
+
+    /// A hashable key for one vertex's values, floats snapped to a grid of size `epsilon`.
+    pub fn vertex_key(&self, vertex: usize, components: usize, epsilon: f32) -> Vec<i64> {
+        let range = vertex * components..(vertex + 1) * components;
+        match self {
+            AttributeData::Float(data) => data[range].iter().map(|x| {
+                if epsilon > 0.0 { (x / epsilon).round() as i64 } else { (x + 0.0).to_bits() as i64 }
+            }).collect(),
+            AttributeData::Byte(data) => data[range].iter().map(|x| *x as i64).collect(),
+            AttributeData::UnsignedByte(data) => data[range].iter().map(|x| *x as i64).collect(),
+            AttributeData::Short(data) => data[range].iter().map(|x| *x as i64).collect(),
+            AttributeData::UnsignedShort(data) => data[range].iter().map(|x| *x as i64).collect(),
+        }
+    }
+}
+
+fn gather_slice<T: Copy>(data: &[T], verticies: &[usize], components: usize) -> Vec<T> {
+    verticies.iter().flat_map(|v| data[v * components..(v + 1) * components].iter().copied()).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexAttribute {
+    /// The attribute name in the vertex shader.
+    pub name: String,
+    pub ty: AttributeType,
+    pub components: i32,
+    /// Map integer values into `[0, 1]` or `[-1, 1]` rather than converting them directly to floats.
+    pub normalized: bool,
+    /// Index of the vertex buffer the attribute is read from.
+    pub buffer: usize,
+    /// Bytes from the start of a vertex to this attribute.
+    pub offset: i32,
+    /// Bytes between consecutive verticies, zero when the attribute is tightly packed on its own.
+    pub stride: i32,
+}
+
+/// Describes how vertex attributes are laid out across one or more buffers.
+/// Shaders linked with a layout have each attribute pinned to its position in the list, which
+/// is why `position` comes first in the standard layout: WebGL wants attribute 0 always enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new() -> VertexLayout {
+        VertexLayout { attributes: Vec::new() }
+    }
+
+    /// The attributes every `Mesh` can provide, each in its own buffer.
+    pub fn standard() -> VertexLayout {
+        let mut layout = VertexLayout::new();
+        layout.add("position", AttributeType::Float, 3, false);
+        layout.add("normal", AttributeType::Float, 3, false);
+        layout.add("color", AttributeType::Float, 4, false);
+        layout.add("texcoord", AttributeType::Float, 2, false);
+        layout
+    }
+
+    /// Append an attribute tightly packed into a buffer of its own.
+    pub fn add(&mut self, name: &str, ty: AttributeType, components: i32, normalized: bool) -> &mut Self {
+        let buffer = self.num_buffers();
+        self.attributes.push(VertexAttribute {
+            name: name.to_string(),
+            ty,
+            components,
+            normalized,
+            buffer,
+            offset: 0,
+            stride: 0,
+        });
+        self
+    }
+
+    pub fn push(&mut self, attribute: VertexAttribute) {
+        self.attributes.push(attribute);
+    }
+
+    /// The same attributes packed one after another into a single buffer,
+    /// each starting on a four byte boundary as WebGL requires.
+    pub fn interleaved(&self) -> VertexLayout {
+        let mut offset = 0;
+        let mut attributes = Vec::new();
+        for attribute in &self.attributes {
+            attributes.push(VertexAttribute { buffer: 0, offset, ..attribute.clone() });
+            offset += align4(attribute.ty.size() * attribute.components);
+        }
+
+        for attribute in attributes.iter_mut() {
+            attribute.stride = offset;
+        }
+
+        VertexLayout { attributes }
+    }
+
+    pub fn get_attributes(&self) -> &Vec<VertexAttribute> { &self.attributes }
+
+    pub fn get(&self, name: &str) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    /// The location an attribute is bound to when a shader is linked with this layout.
+    pub fn location(&self, name: &str) -> Option<u32> {
+        self.attributes.iter().position(|a| a.name == name).map(|i| i as u32)
+    }
+
+    pub fn num_buffers(&self) -> usize {
+        self.attributes.iter().map(|a| a.buffer + 1).max().unwrap_or(0)
+    }
+
+    /// Bytes per vertex in a buffer.
+    pub fn vertex_size(&self, buffer: usize) -> i32 {
+        self.attributes.iter()
+            .filter(|a| a.buffer == buffer)
+            .map(|a| if a.stride > 0 { a.stride } else { a.ty.size() * a.components })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn align4(size: i32) -> i32 {
+    (size + 3) & !3
+}
@@ -1,4 +1,10 @@
-use nalgebra::Vector3;
+use nalgebra::{Vector2, Vector3, Vector4};
+use crate::render::shadow::ShadowSettings;
+use crate::render::error::RenderError;
+use crate::shader::Shader;
+
+/// The most lights the built-in shaders loop over, defined as `MAX_LIGHTS` for shaders by the `Preprocessor`.
+pub const MAX_LIGHTS: usize = 8;
 
 pub trait Light<T> {
     fn color(&self) -> Vector3<T>;
@@ -13,8 +19,18 @@ pub trait LightDirection<T> {
     fn direction(&self) -> Vector3<T>;
 }
 
+pub trait LightRange<T> {
+    fn range(&self) -> T;
+}
+
+pub trait LightCone<T> {
+    fn inner_cone(&self) -> T;
+    fn outer_cone(&self) -> T;
+}
+
 pub type AmbientLight = AmbientLightGen<f32>;
 
+#[derive(Debug, Clone, Copy)]
 pub struct AmbientLightGen<T> {
     pub color: Vector3<T>,
     pub intensity: T,
@@ -33,10 +49,14 @@ where T: Copy {
 
 pub type DirectionalLight = DirectionalLightGen<f32>;
 
+#[derive(Debug, Clone, Copy)]
 pub struct DirectionalLightGen<T> {
     pub color: Vector3<T>,
     pub intensity: T,
+    /// Direction pointing towards the light.
     pub direction: Vector3<T>,
+    /// Cast shadows with these settings, see `ShadowPass`.
+    pub shadow: Option<ShadowSettings>,
 }
 
 impl <T> Light<T> for DirectionalLightGen<T>
@@ -59,10 +79,13 @@ where T: Copy {
 
 pub type PointLight = PointLightGen<f32>;
 
+#[derive(Debug, Clone, Copy)]
 pub struct PointLightGen<T> {
     pub color: Vector3<T>,
     pub intensity: T,
     pub position: Vector3<T>,
+    /// Distance at which the light has faded out completely, zero for no cutoff.
+    pub range: T,
 }
 
 impl <T> Light<T> for PointLightGen<T>
@@ -83,13 +106,30 @@ where T: Copy {
     }
 }
 
+impl <T> LightRange<T> for PointLightGen<T>
+where T: Copy {
+    fn range(&self) -> T {
+        self.range
+    }
+}
+
 pub type SpotLight = SpotLightGen<f32>;
 
+#[derive(Debug, Clone, Copy)]
 pub struct SpotLightGen<T> {
     pub color: Vector3<T>,
     pub intensity: T,
     pub position: Vector3<T>,
+    /// Direction the spot is pointing in.
     pub direction: Vector3<T>,
+    /// Distance at which the light has faded out completely, zero for no cutoff.
+    pub range: T,
+    /// Half angle in radians inside which the spot is at full intensity.
+    pub inner_cone: T,
+    /// Half angle in radians outside of which the spot does not reach.
+    pub outer_cone: T,
+    /// Cast shadows with these settings, see `ShadowPass`.
+    pub shadow: Option<ShadowSettings>,
 }
 
 impl <T> Light<T> for SpotLightGen<T>
@@ -116,3 +156,209 @@ where T: Copy {
         self.direction
     }
 }
+
+impl <T> LightRange<T> for SpotLightGen<T>
+where T: Copy {
+    fn range(&self) -> T {
+        self.range
+    }
+}
+
+impl <T> LightCone<T> for SpotLightGen<T>
+where T: Copy {
+    fn inner_cone(&self) -> T {
+        self.inner_cone
+    }
+
+    fn outer_cone(&self) -> T {
+        self.outer_cone
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LightSource {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl From<DirectionalLight> for LightSource {
+    fn from(light: DirectionalLight) -> Self {
+        LightSource::Directional(light)
+    }
+}
+
+impl From<PointLight> for LightSource {
+    fn from(light: PointLight) -> Self {
+        LightSource::Point(light)
+    }
+}
+
+impl From<SpotLight> for LightSource {
+    fn from(light: SpotLight) -> Self {
+        LightSource::Spot(light)
+    }
+}
+
+impl LightSource {
+    /// The shadow settings of lights that cast shadows. Point lights never do.
+    pub fn shadow(&self) -> Option<&ShadowSettings> {
+        match self {
+            LightSource::Directional(light) => light.shadow.as_ref(),
+            LightSource::Point(_) => None,
+            LightSource::Spot(light) => light.shadow.as_ref(),
+        }
+    }
+
+    /// Pack the light into the `lightColor`, `lightPosition`, `lightSpot` and `lightFalloff`
+    /// uniform layout used by the built-in shaders.
+    fn pack(&self) -> (Vector4<f32>, Vector4<f32>, Vector4<f32>, Vector2<f32>) {
+        // A cone this wide lets everything through, for lights that are not spots
+        let no_cone = (Vector3::zeros().push(-2.0), -1.0);
+
+        match self {
+            LightSource::Directional(light) => (
+                light.color.push(light.intensity),
+                light.direction.normalize().push(0.0),
+                no_cone.0,
+                Vector2::new(0.0, no_cone.1),
+            ),
+            LightSource::Point(light) => (
+                light.color.push(light.intensity),
+                light.position.push(1.0),
+                no_cone.0,
+                Vector2::new(light.range, no_cone.1),
+            ),
+            LightSource::Spot(light) => {
+                let outer = light.outer_cone.cos();
+                // Keep the cone edges apart, smoothstep is undefined when they meet
+                let inner = light.inner_cone.cos().max(outer + 0.0001);
+                (
+                    light.color.push(light.intensity),
+                    light.position.push(1.0),
+                    light.direction.normalize().push(outer),
+                    Vector2::new(light.range, inner),
+                )
+            }
+        }
+    }
+}
+
+/// Every light affecting a draw, uploaded to a shader in one go.
+#[derive(Debug, Clone)]
+pub struct LightSet {
+    pub ambient: AmbientLight,
+    lights: Vec<LightSource>,
+    max_lights: usize,
+}
+
+impl LightSet {
+    /// Create an empty set, `max_lights` should match the `MAX_LIGHTS` the shader was built with.
+    pub fn new(max_lights: usize) -> LightSet {
+        LightSet {
+            ambient: AmbientLight { color: Vector3::new(1.0, 1.0, 1.0), intensity: 0.0 },
+            lights: Vec::new(),
+            max_lights,
+        }
+    }
+
+    /// Add a light to the set, failing once the set is full.
+    pub fn add<L: Into<LightSource>>(&mut self, light: L) -> Result<(), RenderError> {
+        if self.lights.len() >= self.max_lights {
+            return Err(RenderError::TooManyLights(self.max_lights));
+        }
+
+        self.lights.push(light.into());
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn get_lights(&self) -> &Vec<LightSource> { &self.lights }
+    pub fn get_lights_mut(&mut self) -> &mut Vec<LightSource> { &mut self.lights }
+    pub fn max_lights(&self) -> usize { self.max_lights }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Upload the ambient light and every light in the set to the currently bound shader.
+    /// Uniforms the shader doesn't use are skipped, so one set can be applied to every shader.
+    pub fn apply(&self, shader: &Shader) -> Result<(), RenderError> {
+        if shader.has_uniform("ambientLightColor") {
+            shader.set_uniform4f("ambientLightColor", self.ambient.color.push(self.ambient.intensity))?;
+        }
+        if shader.has_uniform("lightCount") {
+            shader.set_uniform1i("lightCount", self.lights.len() as i32)?;
+        }
+
+        let mut colors = Vec::with_capacity(self.lights.len());
+        let mut positions = Vec::with_capacity(self.lights.len());
+        let mut spots = Vec::with_capacity(self.lights.len());
+        let mut falloffs = Vec::with_capacity(self.lights.len());
+        for light in &self.lights {
+            let (color, position, spot, falloff) = light.pack();
+            colors.push(color);
+            positions.push(position);
+            spots.push(spot);
+            falloffs.push(falloff);
+        }
+
+        if shader.has_uniform("lightColor") {
+            shader.set_uniform4f_array("lightColor", &colors)?;
+        }
+        if shader.has_uniform("lightPosition") {
+            shader.set_uniform4f_array("lightPosition", &positions)?;
+        }
+        if shader.has_uniform("lightSpot") {
+            shader.set_uniform4f_array("lightSpot", &spots)?;
+        }
+        if shader.has_uniform("lightFalloff") {
+            shader.set_uniform2f_array("lightFalloff", &falloffs)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+    use crate::render::Renderer;
+    use crate::render::soft::SoftRenderer;
+    use crate::shader::{UniformData, SHADER_SIMPLE_VERT};
+
+    #[test]
+    fn apply_skips_unused_uniforms() {
+        // Only uses the light colors, as if the compiler had dropped the rest
+        let fragment = "precision mediump float;\nuniform int lightCount;\nuniform vec4 lightColor[4];\nvoid main() {\n    gl_FragColor = lightColor[0];\n}\n";
+        let render = SoftRenderer::new(1, 1);
+        let shader = render.create_shader(SHADER_SIMPLE_VERT, fragment).unwrap();
+
+        let mut lights = LightSet::new(4);
+        let light = DirectionalLight { color: vector!(1.0, 0.5, 0.25), intensity: 2.0, direction: Vector3::y(), shadow: None };
+        lights.add(light).unwrap();
+        lights.add(light).unwrap();
+        lights.apply(&shader).unwrap();
+
+        let program = shader.soft_program().unwrap();
+        assert_eq!(program.get_uniform("lightCount"), Some(UniformData::Int(2)));
+        assert_eq!(program.get_uniform("lightColor[1]"), Some(UniformData::Vec4(vector!(1.0, 0.5, 0.25, 2.0))));
+        assert_eq!(program.get_uniform("lightPosition[0]"), None);
+    }
+
+    #[test]
+    fn add_fails_once_full() {
+        let mut lights = LightSet::new(1);
+        let light = DirectionalLight { color: Vector3::repeat(1.0), intensity: 1.0, direction: Vector3::y(), shadow: None };
+        lights.add(light).unwrap();
+        assert_eq!(lights.add(light), Err(RenderError::TooManyLights(1)));
+        assert_eq!(lights.len(), 1);
+    }
+}
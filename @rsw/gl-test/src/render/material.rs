@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use nalgebra::{ Vector2, Vector3, Vector4, Matrix3, Matrix4 };
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::light::LightSet;
+use crate::render::mesh::Mesh;
+use crate::render::shadow::ShadowPass;
+use crate::render::texture::Texture;
+use crate::shader::{Shader, UniformData, UniformValue};
+
+#[derive(Clone)]
+pub enum MaterialParam {
+    Value(UniformData),
+    Texture(Rc<Texture>),
+}
+
+/// A shader together with the uniform values and textures it should be drawn with.
+/// Per object values such as the transform are not part of the material, see `apply_transform`.
+#[derive(Clone)]
+pub struct Material {
+    shader: Rc<Shader>,
+    params: HashMap<String, MaterialParam>,
+}
+
+impl Material {
+    pub fn new(shader: Rc<Shader>) -> Material {
+        Material { shader, params: HashMap::new() }
+    }
+
+    pub fn get_shader(&self) -> &Rc<Shader> {
+        &self.shader
+    }
+
+    pub fn get_params(&self) -> &HashMap<String, MaterialParam> {
+        &self.params
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MaterialParam> {
+        self.params.get(name)
+    }
+
+    /// Set a parameter from any uniform value, see `UniformValue`.
+    pub fn set<T: UniformValue>(&mut self, name: &str, val: T) {
+        self.params.insert(name.to_string(), MaterialParam::Value(val.to_uniform_data()));
+    }
+
+    pub fn set_int(&mut self, name: &str, x: i32) { self.set(name, UniformData::Int(x)); }
+    pub fn set_bool(&mut self, name: &str, b: bool) { self.set(name, UniformData::Bool(b)); }
+    pub fn set_float(&mut self, name: &str, x: f32) { self.set(name, UniformData::Float(x)); }
+    pub fn set_vec2(&mut self, name: &str, val: Vector2<f32>) { self.set(name, UniformData::Vec2(val)); }
+    pub fn set_vec3(&mut self, name: &str, val: Vector3<f32>) { self.set(name, UniformData::Vec3(val)); }
+    pub fn set_vec4(&mut self, name: &str, val: Vector4<f32>) { self.set(name, UniformData::Vec4(val)); }
+    pub fn set_matrix3(&mut self, name: &str, val: Matrix3<f32>) { self.set(name, UniformData::Mat3(val)); }
+    pub fn set_matrix4(&mut self, name: &str, val: Matrix4<f32>) { self.set(name, UniformData::Mat4(val)); }
+
+    pub fn set_texture(&mut self, name: &str, texture: Rc<Texture>) {
+        self.params.insert(name.to_string(), MaterialParam::Texture(texture));
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<MaterialParam> {
+        self.params.remove(name)
+    }
+
+    /// Bind the shader and upload every parameter.
+    pub fn apply(&self, render: &dyn Renderer) -> Result<(), RenderError> {
+        render.set_shader(Some(&self.shader));
+        self.apply_params()
+    }
+
+    /// Upload every parameter to the shader, assuming it is already bound.
+    /// Textures take units counting up from zero in name order. Every parameter is attempted
+    /// even when one fails, such as a uniform the shader does not declare, before returning the first error.
+    pub fn apply_params(&self) -> Result<(), RenderError> {
+        let mut names: Vec<&String> = self.params.keys().collect();
+        names.sort();
+
+        let mut result = Ok(());
+        let mut unit = 0;
+        for name in names {
+            let applied = match &self.params[name] {
+                MaterialParam::Value(val) => self.shader.set_uniform_data(name, *val),
+                MaterialParam::Texture(texture) => {
+                    unit += 1;
+                    self.shader.set_texture(name, texture, unit - 1)
+                }
+            };
+            result = result.and(applied);
+        }
+
+        result
+    }
+}
+
+/// Set the `mvp`, `model` and `normalMatrix` uniforms for an object.
+/// Shaders that do not declare some of them are not an error.
+pub fn apply_transform(shader: &Shader, view_projection: Matrix4<f32>, model: Matrix4<f32>) {
+    if shader.has_uniform("mvp") {
+        shader.set_uniform_matrix4f("mvp", view_projection * model).ok();
+    }
+    if shader.has_uniform("model") {
+        shader.set_uniform_matrix4f("model", model).ok();
+    }
+    if shader.has_uniform("normalMatrix") {
+        let normal_matrix = model.transpose().try_inverse().unwrap_or(Matrix4::identity());
+        shader.set_uniform_matrix4f("normalMatrix", normal_matrix).ok();
+    }
+}
+
+struct Draw<'a> {
+    mesh: &'a Mesh,
+    material: &'a Material,
+    model: Matrix4<f32>,
+    receive_shadows: bool,
+}
+
+/// Collects the draws for a frame so they can be submitted grouped by shader and then by material,
+/// switching programs and re-uploading parameters as rarely as possible.
+/// Draws sharing a material keep the order they were pushed in.
+pub struct DrawQueue<'a> {
+    draws: Vec<Draw<'a>>,
+    shadows: Option<&'a ShadowPass>,
+}
+
+impl<'a> DrawQueue<'a> {
+    pub fn new() -> DrawQueue<'a> {
+        DrawQueue { draws: Vec::new(), shadows: None }
+    }
+
+    pub fn push(&mut self, mesh: &'a Mesh, material: &'a Material, model: Matrix4<f32>) {
+        self.push_with_shadows(mesh, material, model, true);
+    }
+
+    /// Queue a draw, choosing whether shadows from `set_shadows` fall on it.
+    pub fn push_with_shadows(&mut self, mesh: &'a Mesh, material: &'a Material, model: Matrix4<f32>, receive_shadows: bool) {
+        self.draws.push(Draw { mesh, material, model, receive_shadows });
+    }
+
+    /// Shadow maps to apply to each shader along with the lights, already drawn for the same lights.
+    pub fn set_shadows(&mut self, shadows: &'a ShadowPass) {
+        self.shadows = Some(shadows);
+    }
+
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Draw everything queued, applying `lights` to each shader as it is bound.
+    pub fn submit(mut self, render: &dyn Renderer, view_projection: Matrix4<f32>, lights: Option<&LightSet>) {
+        self.draws.sort_by_key(|d| (Rc::as_ptr(&d.material.shader) as usize, d.material as *const Material as usize));
+
+        let mut shader: Option<*const Shader> = None;
+        let mut material: Option<*const Material> = None;
+        for draw in &self.draws {
+            let draw_shader = Rc::as_ptr(&draw.material.shader);
+            if shader != Some(draw_shader) {
+                render.set_shader(Some(&draw.material.shader));
+                // Unlit shaders are mixed in freely, so only light the shaders that take lights
+                if let Some(lights) = lights.filter(|_| draw.material.shader.has_uniform("lightCount")) {
+                    lights.apply(&draw.material.shader).ok();
+                }
+                if let Some(shadows) = self.shadows.filter(|_| draw.material.shader.has_uniform("receiveShadows")) {
+                    shadows.apply(&draw.material.shader).ok();
+                }
+                shader = Some(draw_shader);
+            }
+
+            let draw_material: *const Material = draw.material;
+            if material != Some(draw_material) {
+                draw.material.apply_params().ok();
+                material = Some(draw_material);
+            }
+
+            apply_transform(&draw.material.shader, view_projection, draw.model);
+            if self.shadows.is_some() && draw.material.shader.has_uniform("receiveShadows") {
+                draw.material.shader.set_uniform_bool("receiveShadows", draw.receive_shadows).ok();
+            }
+            render.draw_mesh(draw.mesh);
+        }
+    }
+}
+
+impl<'a> Default for DrawQueue<'a> {
+    fn default() -> Self {
+        DrawQueue::new()
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+use nalgebra::Vector3;
+use js_sys::Float32Array;
+use web_sys::WebGlRenderingContext;
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::resource::BufferUsage;
+use super::as_floats;
+
+/// How long uploading vertex positions took one float at a time, the way meshes used to be copied
+/// into a `Float32Array`, against handing the whole slice over at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UploadBenchmark {
+    pub verticies: usize,
+    pub iterations: u32,
+    /// Milliseconds per upload.
+    pub per_element: f64,
+    pub bulk: f64,
+}
+
+impl fmt::Display for UploadBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} verticies: {:.3}ms per element, {:.3}ms bulk ({:.1}x faster) over {} uploads",
+            self.verticies, self.per_element, self.bulk, self.per_element / self.bulk.max(f64::EPSILON), self.iterations,
+        )
+    }
+}
+
+/// Upload `verticies` positions `iterations` times each way. Needs GL buffers, as the cost
+/// being measured is the calls across into JS.
+pub fn benchmark_upload(render: &dyn Renderer, verticies: usize, iterations: u32) -> Result<UploadBenchmark, RenderError> {
+    let performance = web_sys::window()
+        .and_then(|window| window.performance())
+        .ok_or_else(|| RenderError::Unsupported(String::from("timing uploads without window.performance")))?;
+    let positions: Vec<Vector3<f32>> = (0..verticies).map(|i| Vector3::repeat(i as f32)).collect();
+    let floats = as_floats(&positions);
+    let mut buffer = render.create_buffer()?;
+    let target = WebGlRenderingContext::ARRAY_BUFFER;
+    buffer.reserve(target, (floats.len() * 4) as i32, BufferUsage::Dynamic);
+
+    let time = |upload: &dyn Fn()| {
+        let start = performance.now();
+        for _ in 0..iterations {
+            upload();
+        }
+        (performance.now() - start) / iterations.max(1) as f64
+    };
+
+    let per_element = time(&|| {
+        let array = Float32Array::new_with_length(floats.len() as u32);
+        for (i, x) in floats.iter().enumerate() {
+            array.set_index(i as u32, *x);
+        }
+        buffer.write(target, 0, &array);
+    });
+    let bulk = time(&|| buffer.write_floats(target, 0, floats));
+
+    Ok(UploadBenchmark { verticies, iterations, per_element, bulk })
+}
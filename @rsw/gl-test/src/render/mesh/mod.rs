@@ -0,0 +1,658 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
+use nalgebra::*;
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::gl2::VertexArray;
+use crate::render::resource::{BufferUsage, GpuBuffer};
+use crate::render::layout::{AttributeData, AttributeType, VertexLayout};
+use web_sys::{WebGlBuffer, WebGlRenderingContext};
+use js_sys::{Uint16Array, Uint32Array};
+
+pub mod primitives;
+#[cfg(feature = "bench")]
+pub mod bench;
+
+pub type Mesh = MeshGen<Vector3<f32>, Vector3<f32>, Vector4<f32>, Vector2<f32>>;
+
+/// How much each face contributes when averaging smooth normals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// Larger faces pull the normal further towards themselves.
+    Area,
+    /// Faces contribute by the angle of their corner at the vertex, independent of tessellation.
+    Angle,
+}
+
+/// Names used by the standard attributes, which custom attributes may not reuse.
+const STANDARD_ATTRIBUTES: [&str; 4] = ["position", "normal", "color", "texcoord"];
+
+/// A vertex attribute beyond the standard ones, such as tangents or bone weights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomAttribute {
+    pub name: String,
+    pub components: i32,
+    pub normalized: bool,
+    pub data: AttributeData,
+}
+
+#[derive(Debug)]
+pub struct MeshGen<V, N, C, T> {
+    verticies: Vec<V>,
+    normals: Vec<N>,
+    colors: Vec<C>,
+    texcoords: Vec<T>,
+    indicies: Vec<u32>,
+    attributes: Vec<CustomAttribute>,
+    buffers: Vec<GpuBuffer>,
+    layout: VertexLayout,
+    vertex_array: RefCell<Option<VertexArray>>,
+    index_buffer: Option<GpuBuffer>,
+    index_type: u32,
+    /// The verticies of each attribute changed since the buffers were last updated, by attribute name.
+    dirty: Vec<(String, Range<usize>)>,
+    /// How often the mesh is expected to change, see `update_buffers`.
+    pub usage: BufferUsage,
+    pub use_normals: bool,
+    pub use_colors: bool,
+    pub use_texcoords: bool,
+    pub use_indicies: bool,
+    /// Pack every attribute into a single buffer rather than one buffer each.
+    pub interleave: bool,
+    pub draw_mode: u32,
+}
+
+impl<V, N, C, T> MeshGen<V, N, C, T> {
+    pub fn new() -> Self {
+        MeshGen {
+            verticies: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            texcoords: Vec::new(),
+            indicies: Vec::new(),
+            attributes: Vec::new(),
+            buffers: Vec::new(),
+            layout: VertexLayout::new(),
+            vertex_array: RefCell::new(None),
+            index_buffer: None,
+            index_type: WebGlRenderingContext::UNSIGNED_SHORT,
+            dirty: Vec::new(),
+            usage: BufferUsage::Static,
+            use_normals: false,
+            use_colors: false,
+            use_texcoords: false,
+            use_indicies: false,
+            interleave: false,
+            draw_mode: WebGlRenderingContext::TRIANGLES,
+        }
+    }
+
+    /// Remove every vertex, index and custom attribute, keeping the settings and GPU buffers.
+    /// Rebuilding a mesh this way reuses its buffers on the next `update_buffers` instead of allocating new ones.
+    pub fn clear(&mut self) {
+        self.verticies.clear();
+        self.normals.clear();
+        self.colors.clear();
+        self.texcoords.clear();
+        self.indicies.clear();
+        self.attributes.clear();
+        self.dirty.clear();
+    }
+
+    pub fn add_vertex(&mut self, vert: V) {
+        self.verticies.push(vert);
+        self.mark_dirty("position", self.verticies.len() - 1..self.verticies.len());
+    }
+
+    pub fn add_normal(&mut self, norm: N) {
+        self.normals.push(norm);
+        self.mark_dirty("normal", self.normals.len() - 1..self.normals.len());
+    }
+
+    pub fn add_color(&mut self, color: C) {
+        self.colors.push(color);
+        self.mark_dirty("color", self.colors.len() - 1..self.colors.len());
+    }
+
+    pub fn add_texcoord(&mut self, coord: T) {
+        self.texcoords.push(coord);
+        self.mark_dirty("texcoord", self.texcoords.len() - 1..self.texcoords.len());
+    }
+
+    /// Move an existing vertex, only uploading that part of the buffer on the next update.
+    pub fn set_vertex(&mut self, index: usize, vert: V) {
+        self.verticies[index] = vert;
+        self.mark_dirty("position", index..index + 1);
+    }
+
+    pub fn set_normal(&mut self, index: usize, norm: N) {
+        self.normals[index] = norm;
+        self.mark_dirty("normal", index..index + 1);
+    }
+
+    pub fn set_color(&mut self, index: usize, color: C) {
+        self.colors[index] = color;
+        self.mark_dirty("color", index..index + 1);
+    }
+
+    pub fn set_texcoord(&mut self, index: usize, coord: T) {
+        self.texcoords[index] = coord;
+        self.mark_dirty("texcoord", index..index + 1);
+    }
+
+    /// Flag a range of verticies of an attribute as changed, for `update_buffers` to upload.
+    /// The setters do this themselves, this is for attribute data changed some other way.
+    pub fn mark_dirty(&mut self, name: &str, range: Range<usize>) {
+        match self.dirty.iter_mut().find(|(n, _)| n == name) {
+            Some((_, dirty)) => *dirty = dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => self.dirty.push((name.to_string(), range)),
+        }
+    }
+
+    /// Flag every attribute as changed, for when the verticies were rearranged.
+    pub fn mark_all_dirty(&mut self) {
+        let names: Vec<String> = STANDARD_ATTRIBUTES.iter().map(|n| n.to_string())
+            .chain(self.attributes.iter().map(|a| a.name.clone()))
+            .collect();
+        for name in names {
+            self.mark_dirty(&name, 0..usize::MAX);
+        }
+    }
+
+    pub fn add_index(&mut self, index: u32) {
+        self.indicies.push(index);
+    }
+
+    pub fn add_verticies(&mut self, verts: Vec<V>) {
+        for vert in verts {
+            self.add_vertex(vert);
+        }
+    }
+
+    pub fn add_normals(&mut self, norms: Vec<N>) {
+        for norm in norms {
+            self.add_normal(norm);
+        }
+    }
+
+    pub fn add_colors(&mut self, colors: Vec<C>) {
+        for color in colors {
+            self.add_color(color);
+        }
+    }
+
+    pub fn add_texcoords(&mut self, coords: Vec<T>) {
+        for coord in coords {
+            self.add_texcoord(coord);
+        }
+    }
+
+    pub fn add_indicies(&mut self, indicies: Vec<u32>) {
+        for index in indicies {
+            self.add_index(index);
+        }
+    }
+
+    /// Add or replace a custom attribute, `components` values per vertex.
+    /// Fails if `name` is one of the standard attributes.
+    pub fn set_attribute(&mut self, name: &str, components: i32, normalized: bool, data: AttributeData) -> Result<(), ()> {
+        if STANDARD_ATTRIBUTES.contains(&name) {
+            return Err(());
+        }
+
+        let attribute = CustomAttribute { name: name.to_string(), components, normalized, data };
+        match self.attributes.iter_mut().find(|a| a.name == name) {
+            Some(existing) => *existing = attribute,
+            None => self.attributes.push(attribute),
+        }
+        self.mark_dirty(name, 0..usize::MAX);
+        Ok(())
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<CustomAttribute> {
+        let index = self.attributes.iter().position(|a| a.name == name)?;
+        Some(self.attributes.remove(index))
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&CustomAttribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    pub fn get_attributes(&self) -> &Vec<CustomAttribute> { &self.attributes }
+
+    pub fn get_verticies(&self) -> &Vec<V> { &self.verticies }
+    pub fn get_normals(&self) -> &Vec<N> { &self.normals }
+    pub fn get_colors(&self) -> &Vec<C> { &self.colors }
+    pub fn get_texcoords(&self) -> &Vec<T> { &self.texcoords }
+    pub fn get_indicies(&self) -> &Vec<u32> { &self.indicies }
+
+    pub fn num_verticies(&self) -> i32 {
+        self.verticies.len() as i32
+    }
+
+    pub fn num_indicies(&self) -> i32 {
+        self.indicies.len() as i32
+    }
+
+    pub fn using_normals(&self) -> bool { self.use_normals }
+    pub fn using_colors(&self) -> bool { self.use_colors }
+    pub fn using_texcoords(&self) -> bool { self.use_texcoords }
+    pub fn using_indicies(&self) -> bool { self.use_indicies }
+
+    /// The layout the vertex buffers were last uploaded with.
+    pub fn get_layout(&self) -> &VertexLayout { &self.layout }
+    pub fn get_buffer(&self, index: usize) -> Option<&WebGlBuffer> { self.buffers.get(index).map(|b| &**b) }
+
+    /// The WebGL 2 vertex array recorded for this mesh, managed by the renderer.
+    pub(crate) fn vertex_array(&self) -> &RefCell<Option<VertexArray>> { &self.vertex_array }
+
+    /// The buffer an attribute was uploaded into, which is shared by every attribute when interleaved.
+    pub fn get_attribute_buffer(&self, name: &str) -> Option<&WebGlBuffer> {
+        self.layout.get(name).and_then(|a| self.get_buffer(a.buffer))
+    }
+
+    pub fn get_vertex_buffer(&self) -> Option<&WebGlBuffer> { self.get_attribute_buffer("position") }
+    pub fn get_normal_buffer(&self) -> Option<&WebGlBuffer> { self.get_attribute_buffer("normal") }
+    pub fn get_colors_buffer(&self) -> Option<&WebGlBuffer> { self.get_attribute_buffer("color") }
+    pub fn get_texcoord_buffer(&self) -> Option<&WebGlBuffer> { self.get_attribute_buffer("texcoord") }
+    pub fn get_index_buffer(&self) -> Option<&WebGlBuffer> { self.index_buffer.as_deref() }
+
+    /// Either `UNSIGNED_SHORT` or `UNSIGNED_INT`, depending on how the indicies were last uploaded.
+    pub fn get_index_type(&self) -> u32 { self.index_type }
+
+    pub fn draw(&self) {
+        
+    }
+
+    pub fn len(&self) -> usize {
+        self.verticies.len()
+    }
+}
+
+impl Mesh {
+    /// Upload whatever changed since the last update. Fails if a buffer can't be created, or if the
+    /// mesh needs 32-bit indicies and `OES_element_index_uint` is not available, as it couldn't be drawn.
+    /// Renderers without GL buffers, such as the `SoftRenderer`, draw the mesh data as it is and leave nothing to upload.
+    pub fn update_buffers(&mut self, render: &dyn Renderer) -> Result<(), RenderError> {
+        let layout = self.build_layout();
+        // Buffers are kept between updates and only created or freed as the layout needs
+        self.buffers.truncate(layout.num_buffers());
+        while self.buffers.len() < layout.num_buffers() {
+            match render.create_buffer() {
+                Ok(buffer) => self.buffers.push(buffer),
+                // Renderers without GL buffers read the mesh data directly
+                Err(RenderError::Unsupported(_)) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+
+        // A new layout changes what every buffer holds, so it all goes up again
+        let relayout = layout != self.layout;
+        let count = self.verticies.len();
+        for buffer in 0..layout.num_buffers() {
+            let attributes: Vec<_> = layout.get_attributes().iter().filter(|a| a.buffer == buffer).collect();
+            let stride = layout.vertex_size(buffer) as usize;
+
+            let reallocated = self.buffers[buffer].reserve(WebGlRenderingContext::ARRAY_BUFFER, (count * stride) as i32, self.usage);
+            let range = if relayout || reallocated {
+                0..count
+            } else {
+                match self.dirty.iter().filter(|(n, _)| attributes.iter().any(|a| a.name == *n)).map(|(_, r)| r.clone()).reduce(|a, b| a.start.min(b.start)..a.end.max(b.end)) {
+                    Some(dirty) => dirty.start.min(count)..dirty.end.min(count),
+                    None => continue,
+                }
+            };
+            if range.is_empty() {
+                continue;
+            }
+
+            let name = if attributes.len() == 1 { attributes[0].name.as_str() } else { "" };
+            let (target, offset) = (WebGlRenderingContext::ARRAY_BUFFER, (range.start * stride) as i32);
+            let gpu_buffer = &self.buffers[buffer];
+            // Attributes shorter than the verticies only upload what they have, like `pack_buffer` zero filling the rest
+            let clamp = |len: usize| range.start.min(len)..range.end.min(len);
+            match name {
+                "position" => gpu_buffer.write_floats(target, offset, as_floats(&self.verticies[range])),
+                "normal" => gpu_buffer.write_floats(target, offset, as_floats(&self.normals[clamp(self.normals.len())])),
+                "color" => gpu_buffer.write_floats(target, offset, as_floats(&self.colors[clamp(self.colors.len())])),
+                "texcoord" => gpu_buffer.write_floats(target, offset, as_floats(&self.texcoords[clamp(self.texcoords.len())])),
+                _ => {
+                    let bytes = self.pack_buffer(&layout, buffer);
+                    gpu_buffer.write_bytes(target, offset, &bytes[range.start * stride..range.end * stride]);
+                }
+            }
+        }
+        self.dirty.clear();
+
+        if relayout {
+            // The vertex array points into the old layout
+            self.vertex_array.get_mut().take();
+            self.layout = layout;
+        }
+
+        if self.use_indicies {
+            if self.index_buffer.is_none() {
+                self.index_buffer = Some(render.create_buffer()?);
+            }
+
+            self.upload_indicies(render)?;
+        } else {
+            self.index_buffer = None;
+        }
+
+        Ok(())
+    }
+
+    /// The layout the mesh's attributes would be uploaded with in its current state.
+    pub fn build_layout(&self) -> VertexLayout {
+        let mut layout = VertexLayout::new();
+        layout.add("position", AttributeType::Float, 3, false);
+        if self.use_normals {
+            layout.add("normal", AttributeType::Float, 3, false);
+        }
+        if self.use_colors {
+            layout.add("color", AttributeType::Float, 4, false);
+        }
+        if self.use_texcoords {
+            layout.add("texcoord", AttributeType::Float, 2, false);
+        }
+        for attribute in &self.attributes {
+            layout.add(&attribute.name, attribute.data.attribute_type(), attribute.components, attribute.normalized);
+        }
+
+        if self.interleave {
+            layout.interleaved()
+        } else {
+            layout
+        }
+    }
+
+    fn attribute_source(&self, name: &str) -> Option<Cow<'_, AttributeData>> {
+        fn flatten<D: Dim, S: RawStorage<f32, D>>(input: &[Vector<f32, D, S>]) -> Cow<'static, AttributeData> {
+            Cow::Owned(AttributeData::Float(input.iter().flat_map(|v| v.iter().copied()).collect()))
+        }
+
+        match name {
+            "position" => Some(flatten(&self.verticies)),
+            "normal" => Some(flatten(&self.normals)),
+            "color" => Some(flatten(&self.colors)),
+            "texcoord" => Some(flatten(&self.texcoords)),
+            name => self.get_attribute(name).map(|a| Cow::Borrowed(&a.data)),
+        }
+    }
+
+    /// Lay out the bytes of every attribute sharing a buffer, vertex after vertex.
+    fn pack_buffer(&self, layout: &VertexLayout, buffer: usize) -> Vec<u8> {
+        let stride = layout.vertex_size(buffer) as usize;
+        let mut bytes = vec![0; self.verticies.len() * stride];
+
+        for attribute in layout.get_attributes().iter().filter(|a| a.buffer == buffer) {
+            let source = match self.attribute_source(&attribute.name) {
+                Some(source) => source,
+                None => continue,
+            };
+
+            let components = attribute.components as usize;
+            let size = components * attribute.ty.size() as usize;
+            for vert in 0..self.verticies.len() {
+                let start = vert * stride + attribute.offset as usize;
+                source.write_bytes(vert * components, components, &mut bytes[start..start + size]);
+            }
+        }
+
+        bytes
+    }
+
+    /// Upload the index list, using 16-bit indicies whenever they fit.
+    fn upload_indicies(&mut self, render: &dyn Renderer) -> Result<(), RenderError> {
+        let index_buffer = match self.index_buffer.as_mut() {
+            Some(index_buffer) => index_buffer,
+            None => return Ok(()),
+        };
+
+        let (data, size): (js_sys::Object, usize) = if self.indicies.iter().all(|i| *i <= u16::MAX as u32) {
+            let short_indicies: Vec<u16> = self.indicies.iter().map(|i| *i as u16).collect();
+            self.index_type = WebGlRenderingContext::UNSIGNED_SHORT;
+            (Uint16Array::from(short_indicies.as_slice()).into(), 2)
+        } else {
+            if !render.supports_uint_indicies() {
+                return Err(RenderError::Unsupported(format!("indexing {} verticies needs OES_element_index_uint", self.verticies.len())));
+            }
+
+            self.index_type = WebGlRenderingContext::UNSIGNED_INT;
+            (Uint32Array::from(self.indicies.as_slice()).into(), 4)
+        };
+
+        let target = WebGlRenderingContext::ELEMENT_ARRAY_BUFFER;
+        index_buffer.reserve(target, (self.indicies.len() * size) as i32, self.usage);
+        index_buffer.write(target, 0, &data);
+        Ok(())
+    }
+
+    pub fn generate_normals(&mut self) {
+        // A crease angle of zero only merges faces that are perfectly flat with each other
+        if self.use_indicies {
+            self.generate_smooth_normals(0.0, 0.0, NormalWeighting::Area);
+            return;
+        }
+
+        self.normals.clear();
+
+        for i in 0..(self.verticies.len() / 3) {
+            let a = self.verticies[i * 3];
+            let b = self.verticies[(i * 3) + 1];
+            let c = self.verticies[(i * 3) + 2];
+
+            let n = ((b-a).cross(&(c-a))).normalize();
+            self.add_normals(vec![n,n,n]);
+        }
+
+        self.use_normals = true;
+    }
+
+    /// Generate normals averaged across every face touching a vertex.
+    /// Verticies within `epsilon` of each other count as the same point, so seams in flat meshes
+    /// get smoothed too. Faces more than `crease_angle` radians apart from each other are not
+    /// averaged together, keeping hard edges sharp. Indexed verticies that end up needing more
+    /// than one normal are split.
+    pub fn generate_smooth_normals(&mut self, epsilon: f32, crease_angle: f32, weighting: NormalWeighting) {
+        let trigs: Vec<[usize; 3]> = if self.use_indicies {
+            self.indicies.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect()
+        } else {
+            (0..(self.verticies.len() / 3)).map(|i| [i * 3, (i * 3) + 1, (i * 3) + 2]).collect()
+        };
+
+        // Group verticies sharing a position, and remember which face corners touch each group
+        let mut lookup: HashMap<Vec<i64>, usize> = HashMap::new();
+        let groups: Vec<usize> = self.verticies.iter().map(|v| {
+            let next = lookup.len();
+            *lookup.entry(quantize(v, epsilon)).or_insert(next)
+        }).collect();
+
+        let mut corners: Vec<Vec<(usize, usize)>> = vec![Vec::new(); lookup.len()];
+        for (face, trig) in trigs.iter().enumerate() {
+            for (corner, vert) in trig.iter().enumerate() {
+                corners[groups[*vert]].push((face, corner));
+            }
+        }
+
+        let face_normals: Vec<Vector3<f32>> = trigs.iter().map(|[a, b, c]| {
+            let (a, b, c) = (self.verticies[*a], self.verticies[*b], self.verticies[*c]);
+            (b - a).cross(&(c - a))
+        }).collect();
+
+        let corner_weights: Vec<[f32; 3]> = trigs.iter().enumerate().map(|(face, trig)| {
+            [0, 1, 2].map(|corner| match weighting {
+                // The cross product is already scaled by twice the area
+                NormalWeighting::Area => 1.0,
+                NormalWeighting::Angle => {
+                    let p = self.verticies[trig[corner]];
+                    let e1 = self.verticies[trig[(corner + 1) % 3]] - p;
+                    let e2 = self.verticies[trig[(corner + 2) % 3]] - p;
+                    e1.angle(&e2) / face_normals[face].magnitude().max(f32::EPSILON)
+                }
+            })
+        }).collect();
+
+        let min_cos = crease_angle.cos();
+        let mut normals = vec![Vector3::zeros(); self.verticies.len()];
+        let mut assigned = vec![false; self.verticies.len()];
+        let mut split: HashMap<(usize, Vec<i64>), u32> = HashMap::new();
+        let mut duplicated: Vec<usize> = Vec::new();
+
+        for (face, trig) in trigs.iter().enumerate() {
+            let face_dir = face_normals[face].try_normalize(f32::EPSILON);
+
+            for (corner, vert) in trig.iter().enumerate() {
+                let mut sum: Vector3<f32> = Vector3::zeros();
+                for (other, other_corner) in &corners[groups[*vert]] {
+                    let other_dir = face_normals[*other].try_normalize(f32::EPSILON);
+                    let smooth = match (face_dir, other_dir) {
+                        (Some(a), Some(b)) => *other == face || a.dot(&b) >= min_cos - 0.00001,
+                        _ => false,
+                    };
+
+                    if smooth {
+                        sum += face_normals[*other] * corner_weights[*other][*other_corner];
+                    }
+                }
+
+                let normal = sum.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+
+                if !self.use_indicies {
+                    normals[*vert] = normal;
+                    continue;
+                }
+
+                // Give each distinct normal on an indexed vertex its own copy of the vertex
+                let key = (*vert, quantize(&normal, 0.0001));
+                let index = match split.get(&key) {
+                    Some(index) => *index,
+                    None if !assigned[*vert] => {
+                        assigned[*vert] = true;
+                        normals[*vert] = normal;
+                        *vert as u32
+                    }
+                    None => {
+                        self.verticies.push(self.verticies[*vert]);
+                        if self.use_colors {
+                            self.colors.push(self.colors[*vert]);
+                        }
+                        if self.use_texcoords {
+                            self.texcoords.push(self.texcoords[*vert]);
+                        }
+                        duplicated.push(*vert);
+                        normals.push(normal);
+                        (self.verticies.len() - 1) as u32
+                    }
+                };
+                split.insert(key, index);
+                self.indicies[(face * 3) + corner] = index;
+            }
+        }
+
+        for attribute in self.attributes.iter_mut() {
+            let copies = attribute.data.gather(&duplicated, attribute.components as usize);
+            attribute.data.append(copies);
+        }
+
+        self.normals = normals;
+        self.use_normals = true;
+        self.mark_all_dirty();
+    }
+
+    /// Merge verticies whose attributes all match into one and draw them through the index list.
+    /// Attributes are snapped to a grid of size `epsilon` before being compared, an `epsilon`
+    /// of zero only merges exact duplicates. Already indexed meshes are re-welded.
+    pub fn weld(&mut self, epsilon: f32) {
+        let order: Vec<u32> = if self.use_indicies {
+            self.indicies.clone()
+        } else {
+            (0..self.verticies.len() as u32).collect()
+        };
+
+        let mut welded = Mesh::new();
+        let mut lookup: HashMap<Vec<i64>, u32> = HashMap::new();
+        let mut kept: Vec<usize> = Vec::new();
+
+        for i in order {
+            let i = i as usize;
+            let mut key = quantize(&self.verticies[i], epsilon);
+            if self.use_normals {
+                key.extend(quantize(&self.normals[i], epsilon));
+            }
+            if self.use_colors {
+                key.extend(quantize(&self.colors[i], epsilon));
+            }
+            if self.use_texcoords {
+                key.extend(quantize(&self.texcoords[i], epsilon));
+            }
+            for attribute in &self.attributes {
+                key.extend(attribute.data.vertex_key(i, attribute.components as usize, epsilon));
+            }
+
+            let index = *lookup.entry(key).or_insert_with(|| {
+                welded.add_vertex(self.verticies[i]);
+                if self.use_normals {
+                    welded.add_normal(self.normals[i]);
+                }
+                if self.use_colors {
+                    welded.add_color(self.colors[i]);
+                }
+                if self.use_texcoords {
+                    welded.add_texcoord(self.texcoords[i]);
+                }
+                kept.push(i);
+                welded.verticies.len() as u32 - 1
+            });
+            welded.add_index(index);
+        }
+
+        self.verticies = welded.verticies;
+        self.normals = welded.normals;
+        self.colors = welded.colors;
+        self.texcoords = welded.texcoords;
+        self.indicies = welded.indicies;
+        self.use_indicies = true;
+
+        for attribute in self.attributes.iter_mut() {
+            attribute.data = attribute.data.gather(&kept, attribute.components as usize);
+        }
+        self.mark_all_dirty();
+    }
+}
+
+/// Snap each component to a multiple of `epsilon` so nearly equal vectors hash the same.
+fn quantize<D, S>(input: &Vector<f32, D, S>, epsilon: f32) -> Vec<i64>
+    where D: Dim, S: RawStorage<f32, D> {
+    input.iter().map(|x| {
+        if epsilon > 0.0 {
+            (x / epsilon).round() as i64
+        } else {
+            // Fold negative zero into zero so they weld together
+            (x + 0.0).to_bits() as i64
+        }
+    }).collect()
+}
+
+/// View vectors as the floats they are made of, without copying them.
+fn as_floats<const D: usize>(input: &[SVector<f32, D>]) -> &[f32] {
+    // SAFETY: `SVector<f32, D>` is a `#[repr(C)]` matrix over a transparent `[[f32; D]; 1]`,
+    // so a slice of them is `len * D` floats back to back with no padding in between.
+    unsafe { std::slice::from_raw_parts(input.as_ptr() as *const f32, input.len() * D) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::soft::SoftRenderer;
+
+    #[test]
+    fn update_buffers_without_gl() {
+        let mut mesh = primitives::cube(1.0);
+        mesh.update_buffers(&SoftRenderer::new(1, 1)).unwrap();
+        assert!(mesh.buffers.is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use nalgebra::{vector, Vector2, Vector3};
+use crate::render::mesh::Mesh;
+
+// Every shape is centered on the origin with Y up, and comes out indexed with normals and texcoords,
+// ready for `update_buffers`. Front faces wind counterclockwise, as GL expects by default.
+
+/// An axis aligned cube `size` across. Each face has verticies of its own so its normals stay flat,
+/// and is covered by the whole texture.
+pub fn cube(size: f32) -> Mesh {
+    // The normal of each face and the directions its u and v run in
+    let faces = [
+        (Vector3::x(), -Vector3::z(), Vector3::y()),
+        (-Vector3::x(), Vector3::z(), Vector3::y()),
+        (Vector3::y(), Vector3::x(), -Vector3::z()),
+        (-Vector3::y(), Vector3::x(), Vector3::z()),
+        (Vector3::z(), Vector3::x(), Vector3::y()),
+        (-Vector3::z(), -Vector3::x(), Vector3::y()),
+    ];
+
+    let mut mesh = indexed_mesh();
+    for (normal, u, v) in faces {
+        let first = mesh.len() as u32;
+        for t in [1.0, 0.0] {
+            for s in [0.0, 1.0] {
+                let position = (normal + u * (s * 2.0 - 1.0) + v * (t * 2.0 - 1.0)) * size * 0.5;
+                add_vertex(&mut mesh, position, normal, vector!(s, t));
+            }
+        }
+        add_grid(&mut mesh, first, 1, 1);
+    }
+    mesh
+}
+
+/// A `width` by `depth` rectangle on the XZ plane, facing up.
+pub fn plane(width: f32, depth: f32) -> Mesh {
+    grid(width, depth, 1, 1)
+}
+
+/// A plane split into `columns` by `rows` cells, for meshes that are displaced or lit per vertex.
+/// The texture is stretched over the whole grid, with v increasing towards -Z.
+pub fn grid(width: f32, depth: f32, columns: u32, rows: u32) -> Mesh {
+    let (columns, rows) = (columns.max(1), rows.max(1));
+
+    let mut mesh = indexed_mesh();
+    for row in 0..=rows {
+        let t = row as f32 / rows as f32;
+        for column in 0..=columns {
+            let s = column as f32 / columns as f32;
+            let position = vector!((s - 0.5) * width, 0.0, (t - 0.5) * depth);
+            add_vertex(&mut mesh, position, Vector3::y(), vector!(s, 1.0 - t));
+        }
+    }
+    add_grid(&mut mesh, 0, columns, rows);
+    mesh
+}
+
+/// A sphere cut into `segments` slices around the Y axis and `rings` bands from pole to pole.
+/// The texture wraps around it once, with u following the longitude and v the latitude.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+
+    let mut mesh = indexed_mesh();
+    for ring in 0..=rings {
+        let t = ring as f32 / rings as f32;
+        let (sin, cos) = (t * PI).sin_cos();
+        for segment in 0..=segments {
+            let s = segment as f32 / segments as f32;
+            let normal = around(s * TAU) * sin + Vector3::y() * cos;
+            add_vertex(&mut mesh, normal * radius, normal, vector!(s, 1.0 - t));
+        }
+    }
+    add_grid(&mut mesh, 0, segments, rings);
+    mesh
+}
+
+/// A sphere made by splitting every face of an icosahedron into four, `subdivisions` times over.
+/// Its triangles are much more even than a `uv_sphere`'s, but each level quadruples their number.
+/// Texcoords follow the longitude and latitude like a `uv_sphere`'s, except that faces across the seam
+/// reach a u past 1, relying on the texture repeating as it does by default.
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut points: Vec<Vector3<f32>> = [
+        vector!(-1.0, t, 0.0), vector!(1.0, t, 0.0), vector!(-1.0, -t, 0.0), vector!(1.0, -t, 0.0),
+        vector!(0.0, -1.0, t), vector!(0.0, 1.0, t), vector!(0.0, -1.0, -t), vector!(0.0, 1.0, -t),
+        vector!(t, 0.0, -1.0), vector!(t, 0.0, 1.0), vector!(-t, 0.0, -1.0), vector!(-t, 0.0, 1.0),
+    ].iter().map(|p| p.normalize()).collect();
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Neighbouring faces share the point halfway along their common edge
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(((points[a as usize] + points[b as usize]) * 0.5).normalize());
+                points.len() as u32 - 1
+            })
+        };
+
+        faces = faces.iter().flat_map(|&[a, b, c]| {
+            let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+            [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+
+    let mut mesh = indexed_mesh();
+    // A point may need several verticies with different u, see below
+    let mut verticies: HashMap<(u32, u32), u32> = HashMap::new();
+    for face in faces {
+        let mut u = face.map(|i| longitude(&points[i as usize]));
+
+        // Faces crossing the seam would run through the whole texture backwards,
+        // so their points on the near side of it get a u past 1 instead
+        let known = || u.iter().flatten().copied();
+        let spread = known().fold(f32::MIN, f32::max) - known().fold(f32::MAX, f32::min);
+        if spread > 0.5 {
+            for u in u.iter_mut().flatten().filter(|u| **u < 0.5) {
+                *u += 1.0;
+            }
+        }
+
+        // The poles have no longitude, they take the middle of the face's
+        let known: Vec<f32> = u.iter().flatten().copied().collect();
+        let middle = known.iter().sum::<f32>() / known.len() as f32;
+
+        for (index, u) in face.into_iter().zip(u) {
+            let u = u.unwrap_or(middle);
+            let vertex = *verticies.entry((index, u.to_bits())).or_insert_with(|| {
+                let normal = points[index as usize];
+                let v = 0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI;
+                add_vertex(&mut mesh, normal * radius, normal, vector!(u, v));
+                mesh.len() as u32 - 1
+            });
+            mesh.add_index(vertex);
+        }
+    }
+    mesh
+}
+
+/// A closed cylinder standing on the Y axis.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> Mesh {
+    frustum(radius, radius, height, segments)
+}
+
+/// A cone standing on its base, with its tip pointing up.
+pub fn cone(radius: f32, height: f32, segments: u32) -> Mesh {
+    frustum(radius, 0.0, height, segments)
+}
+
+/// A cylinder whose ends have different radii, capped at each end that isn't a point.
+/// The texture wraps around the side once, while each cap gets a disc out of the middle of it.
+pub fn frustum(bottom_radius: f32, top_radius: f32, height: f32, segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let half = height * 0.5;
+
+    let mut mesh = indexed_mesh();
+    for (t, radius, y) in [(0.0, top_radius, half), (1.0, bottom_radius, -half)] {
+        for segment in 0..=segments {
+            let s = segment as f32 / segments as f32;
+            let out = around(s * TAU);
+            // The side leans in by however much narrower the top is
+            let normal = (out * height + Vector3::y() * (bottom_radius - top_radius)).try_normalize(0.0).unwrap_or(out);
+            add_vertex(&mut mesh, out * radius + Vector3::y() * y, normal, vector!(s, 1.0 - t));
+        }
+    }
+    add_grid(&mut mesh, 0, segments, 1);
+
+    if top_radius > 0.0 {
+        add_cap(&mut mesh, top_radius, half, segments, true);
+    }
+    if bottom_radius > 0.0 {
+        add_cap(&mut mesh, bottom_radius, -half, segments, false);
+    }
+    mesh
+}
+
+/// A ring around the Y axis, `radius` out from the center to the middle of a tube `tube_radius` thick.
+/// u runs around the ring and v around the tube.
+pub fn torus(radius: f32, tube_radius: f32, radial_segments: u32, tubular_segments: u32) -> Mesh {
+    let (segments, sides) = (radial_segments.max(3), tubular_segments.max(3));
+
+    let mut mesh = indexed_mesh();
+    for side in 0..=sides {
+        let t = side as f32 / sides as f32;
+        // Starting from the outer edge, going under the ring first
+        let (sin, cos) = (t * TAU).sin_cos();
+        for segment in 0..=segments {
+            let s = segment as f32 / segments as f32;
+            let out = around(s * TAU);
+            let normal = out * cos - Vector3::y() * sin;
+            add_vertex(&mut mesh, out * radius + normal * tube_radius, normal, vector!(s, 1.0 - t));
+        }
+    }
+    add_grid(&mut mesh, 0, segments, sides);
+    mesh
+}
+
+/// A cylinder `height` long with a hemisphere of `radius` on each end, standing on the Y axis
+/// `height + radius * 2` tall. `rings` is the number of bands in each hemisphere.
+/// v follows the distance along its outline, so the texture is stretched evenly over the rounded ends and the side.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let half = height * 0.5;
+    let length = PI * radius + height;
+
+    let mut mesh = indexed_mesh();
+    for row in 0..=rings * 2 + 1 {
+        // The two middle rows are the same latitude, one at each end of the cylinder
+        let (ring, y, offset) = if row <= rings { (row, half, 0.0) } else { (row - 1, -half, height) };
+        let angle = ring as f32 / (rings * 2) as f32 * PI;
+        let (sin, cos) = angle.sin_cos();
+        let t = (angle * radius + offset) / length;
+        for segment in 0..=segments {
+            let s = segment as f32 / segments as f32;
+            let normal = around(s * TAU) * sin + Vector3::y() * cos;
+            add_vertex(&mut mesh, normal * radius + Vector3::y() * y, normal, vector!(s, 1.0 - t));
+        }
+    }
+    add_grid(&mut mesh, 0, segments, rings * 2 + 1);
+    mesh
+}
+
+/// An empty mesh using indicies, normals and texcoords.
+fn indexed_mesh() -> Mesh {
+    let mut mesh = Mesh::new();
+    mesh.use_normals = true;
+    mesh.use_texcoords = true;
+    mesh.use_indicies = true;
+    mesh
+}
+
+fn add_vertex(mesh: &mut Mesh, position: Vector3<f32>, normal: Vector3<f32>, texcoord: Vector2<f32>) {
+    mesh.add_vertex(position);
+    mesh.add_normal(normal);
+    mesh.add_texcoord(texcoord);
+}
+
+/// Index `rows + 1` rows of `columns + 1` verticies each, added one row after another from `first`,
+/// as two triangles per cell. The front is the side from which the columns run to the right and the rows downwards.
+fn add_grid(mesh: &mut Mesh, first: u32, columns: u32, rows: u32) {
+    let stride = columns + 1;
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = first + row * stride + column;
+            let (b, c, d) = (a + 1, a + stride, a + stride + 1);
+            mesh.add_indicies(vec![a, c, b, b, c, d]);
+        }
+    }
+}
+
+/// A disc closing off one end of a `frustum`, facing up or down.
+fn add_cap(mesh: &mut Mesh, radius: f32, y: f32, segments: u32, up: bool) {
+    let (normal, flip) = if up { (Vector3::y(), 1.0) } else { (-Vector3::y(), -1.0) };
+
+    let center = mesh.len() as u32;
+    add_vertex(mesh, Vector3::y() * y, normal, vector!(0.5, 0.5));
+    for segment in 0..segments {
+        let out = around(segment as f32 / segments as f32 * TAU);
+        // Mirrored underneath, so the texture reads the right way around from outside
+        add_vertex(mesh, out * radius + Vector3::y() * y, normal, vector!(0.5 + out.x * 0.5, 0.5 - out.z * 0.5 * flip));
+    }
+
+    for segment in 0..segments {
+        let (a, b) = (center + 1 + segment, center + 1 + (segment + 1) % segments);
+        mesh.add_indicies(if up { vec![center, a, b] } else { vec![center, b, a] });
+    }
+}
+
+/// The direction `angle` radians around the Y axis from +X, counterclockwise seen from above.
+fn around(angle: f32) -> Vector3<f32> {
+    let (sin, cos) = angle.sin_cos();
+    vector!(cos, 0.0, -sin)
+}
+
+/// How far around the Y axis a point on the unit sphere is, from 0 to 1 the same way as `around`.
+/// `None` at the poles, where every longitude meets.
+fn longitude(point: &Vector3<f32>) -> Option<f32> {
+    if point.x.abs() < 1e-6 && point.z.abs() < 1e-6 {
+        return None;
+    }
+    Some((-point.z).atan2(point.x).rem_euclid(TAU) / TAU)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check what every shape promises: indicies in bounds, a unit normal and texcoord per vertex,
+    /// and triangles wound counterclockwise as seen from the side their normals point to.
+    fn check(name: &str, mesh: &Mesh) {
+        let (verticies, normals, texcoords, indicies) = (mesh.get_verticies(), mesh.get_normals(), mesh.get_texcoords(), mesh.get_indicies());
+        assert_eq!(normals.len(), verticies.len(), "{} normals", name);
+        assert_eq!(texcoords.len(), verticies.len(), "{} texcoords", name);
+        assert!(!indicies.is_empty() && indicies.len() % 3 == 0, "{} indicies", name);
+
+        for normal in normals {
+            assert!((normal.norm() - 1.0).abs() < 1e-4, "{} normal {:?}", name, normal);
+        }
+        for triangle in indicies.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            assert!(a < verticies.len() && b < verticies.len() && c < verticies.len(), "{} index out of bounds", name);
+            // The poles of round shapes collapse some triangles to a point or line
+            let face = (verticies[b] - verticies[a]).cross(&(verticies[c] - verticies[a]));
+            if face.norm() > 1e-6 {
+                assert!(face.dot(&(normals[a] + normals[b] + normals[c])) > 0.0, "{} triangle {:?} wound the wrong way", name, triangle);
+            }
+        }
+    }
+
+    /// Round shapes duplicate the verticies along their seam, so no triangle runs back across the texture.
+    /// The coarsest icosphere has faces around the poles covering half of it, but never more.
+    fn check_seam(name: &str, mesh: &Mesh) {
+        let texcoords = mesh.get_texcoords();
+        for triangle in mesh.get_indicies().chunks(3) {
+            let us = triangle.iter().map(|i| texcoords[*i as usize].x);
+            let span = us.clone().fold(f32::MIN, f32::max) - us.fold(f32::MAX, f32::min);
+            assert!(span <= 0.5 + 1e-4, "{} triangle {:?} wraps around the texture", name, triangle);
+        }
+    }
+
+    #[test]
+    fn flat_shapes() {
+        check("cube", &cube(2.0));
+        check("plane", &plane(1.0, 2.0));
+        check("grid", &grid(1.0, 2.0, 4, 3));
+
+        for p in cube(2.0).get_verticies() {
+            assert!((p.abs().max() - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(grid(1.0, 2.0, 4, 3).get_verticies().len(), 5 * 4);
+    }
+
+    #[test]
+    fn spheres() {
+        let sphere = uv_sphere(1.5, 16, 8);
+        check("uv_sphere", &sphere);
+        check_seam("uv_sphere", &sphere);
+
+        for subdivisions in 0..4 {
+            let ico = icosphere(2.0, subdivisions);
+            check("icosphere", &ico);
+            check_seam("icosphere", &ico);
+            for (p, n) in ico.get_verticies().iter().zip(ico.get_normals()) {
+                assert!((p - n * 2.0).norm() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn round_shapes() {
+        check("cylinder", &cylinder(0.5, 2.0, 12));
+        check("cone", &cone(0.5, 2.0, 12));
+        check("frustum", &frustum(0.5, 0.25, 1.0, 12));
+
+        let torus = torus(1.0, 0.25, 24, 12);
+        check("torus", &torus);
+        check_seam("torus", &torus);
+        for p in torus.get_verticies() {
+            let ring = (p.x * p.x + p.z * p.z).sqrt();
+            assert!((((ring - 1.0).powi(2) + p.y * p.y).sqrt() - 0.25).abs() < 1e-5);
+        }
+
+        let capsule = capsule(0.5, 1.0, 12, 4);
+        check("capsule", &capsule);
+        check_seam("capsule", &capsule);
+    }
+}
@@ -1,34 +1,82 @@
-use web_sys::{WebGlRenderingContext, WebGlBuffer};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use web_sys::{AngleInstancedArrays, WebGlRenderingContext, WebGl2RenderingContext, WebGlFramebuffer, HtmlImageElement, ImageBitmap};
 use wasm_bindgen::prelude::*;
 use nalgebra::{ Vector1, Vector2, Vector3, Vector4, Matrix2, Matrix3, Matrix4 };
 use crate::shader::Shader;
+use crate::shader::preprocess::Preprocessor;
 use crate::Mesh;
+use crate::scene::Scene;
+use texture::{Texture, TextureOptions};
+use layout::VertexLayout;
+use target::{RenderTarget, RenderTargetOptions};
+use gl2::{GlBackend, UniformBuffer, VertexArray};
+use instance::{InstanceBuffer, attribute_columns};
+use error::RenderError;
+use globals::GlobalUniforms;
+use resource::GpuBuffer;
 
+pub mod error;
+pub mod globals;
+pub mod resource;
 pub mod mesh;
+pub mod layout;
+pub mod gl2;
+pub mod instance;
+pub mod target;
+pub mod post;
+pub mod shadow;
 pub mod light;
+pub mod material;
+pub mod camera;
+pub mod soft;
+pub mod texture;
 
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
-}
+use crate::log;
 
 macro_rules! console_log { ($($t:tt)*) => (log(&format!("[render] {}", &format_args!($($t)*)).to_string())) }
 
-const POSITION_ATTRIBUTE: u32 = 0;
-const NORMAL_ATTRIBUTE: u32 = 1;
-const COLOR_ATTRIBUTE: u32 = 2;
-const TEXCOORD_ATTRIBUTE: u32 = 3;
-
 pub trait Renderer {
-    fn create_shader(&self, vertex: &str, fragment: &str) -> Result<Shader, ()>;
+    fn create_shader(&self, vertex: &str, fragment: &str) -> Result<Shader, RenderError>;
+    fn create_shader_with_layout(&self, vertex: &str, fragment: &str, layout: &VertexLayout) -> Result<Shader, RenderError>;
+    /// Create a shader with extra `#define`s, such as `("USE_TEXTURE", "1")`, on top of the preprocessor's.
+    fn create_shader_with_defines(&self, vertex: &str, fragment: &str, defines: &[(&str, &str)]) -> Result<Shader, RenderError>;
+    /// The preprocessor every shader is run through, to register chunks and defines for shaders created afterwards.
+    fn get_preprocessor(&self) -> &RefCell<Preprocessor>;
+    /// Recompile a shader in place from new sources, preprocessed with the defines it was created with.
+    /// On failure the shader keeps its current program. See `Shader::reload`.
+    fn reload_shader(&self, shader: &Shader, vertex: &str, fragment: &str) -> Result<(), RenderError>;
+    /// Bind a shader for the following draws, uploading the global uniforms it declares.
     fn set_shader(&self, program: Option<&Shader>);
+    /// Replace the global uniforms, which reach each shader the next time it is bound.
+    fn set_globals(&self, globals: GlobalUniforms);
+    fn get_globals(&self) -> GlobalUniforms;
     fn draw_mesh(&self, mesh: &Mesh);
     fn draw_mesh_mode(&self, mesh: &Mesh, draw_mode: u32);
-    fn create_buffer(&self) -> Result<WebGlBuffer, ()>;
+    /// Draw a copy of the mesh for every instance, each reading its own values of the instance
+    /// attributes. Backends without instancing fall back to drawing the copies one by one.
+    fn draw_mesh_instanced(&self, mesh: &Mesh, instances: &InstanceBuffer);
+    /// Whether instanced draws happen in a single call.
+    fn supports_instancing(&self) -> bool;
+    fn draw_scene(&self, scene: &Scene);
+    fn create_buffer(&self) -> Result<GpuBuffer, RenderError>;
+    fn supports_uint_indicies(&self) -> bool;
+    fn create_texture(&self, width: u32, height: u32, data: &[u8], options: &TextureOptions) -> Result<Texture, RenderError>;
+    fn create_texture_from_image(&self, image: &HtmlImageElement, options: &TextureOptions) -> Result<Texture, RenderError>;
+    fn create_texture_from_bitmap(&self, bitmap: &ImageBitmap, options: &TextureOptions) -> Result<Texture, RenderError>;
+    fn create_render_target(&self, width: u32, height: u32, options: &RenderTargetOptions) -> Result<RenderTarget, RenderError>;
+    /// Draw into a render target, or back onto the canvas with `None`. The viewport follows the target's size.
+    fn set_render_target(&self, target: Option<&RenderTarget>);
+    /// Read RGBA8 pixels from the render target being drawn into, or the canvas.
+    /// `(0, 0)` is the bottom left corner and rows are returned bottom up, as in GL.
+    fn read_pixels(&self, x: i32, y: i32, width: i32, height: i32) -> Result<Vec<u8>, RenderError>;
+    /// Clear the color and depth of the render target being drawn into, or the canvas.
     fn clear(&self, color: Vector4<f32>);
     fn begin_render(&self);
     fn end_render(&self);
+    fn enable_depth_test(&self);
+    fn disable_depth_test(&self);
     fn get_width(&self) -> i32;
     fn get_height(&self) -> i32;
     fn aspect(&self) -> f32;
@@ -37,83 +85,204 @@ pub trait Renderer {
     fn get_gl(&self) -> Option<&WebGlRenderingContext>;
 }
 
+/// Renders through WebGL 2 when the browser supports it, otherwise WebGL 1.
+/// Both share the WebGL 1 API, with WebGL 2 adding vertex array objects, uniform buffers,
+/// instancing and multiple render targets on top.
 pub struct GlRenderer {
     gl: WebGlRenderingContext,
+    /// The same context as `gl` when it is a WebGL 2 one.
+    gl2: Option<WebGl2RenderingContext>,
     canvas: Option<web_sys::HtmlCanvasElement>,
+    uint_indicies: bool,
+    /// ANGLE_instanced_arrays, for instancing on WebGL 1.
+    instancing: Option<AngleInstancedArrays>,
+    /// Attribute locations of the bound shader.
+    attributes: RefCell<Rc<HashMap<String, u32>>>,
+    /// Bitmask of the vertex attribute arrays currently enabled.
+    enabled_attributes: Cell<u32>,
+    /// The framebuffer being drawn into and its size, `None` for the canvas.
+    target: RefCell<Option<(WebGlFramebuffer, i32, i32)>>,
+    preprocessor: RefCell<Preprocessor>,
+    globals: Cell<GlobalUniforms>,
 }
 
 impl Renderer for GlRenderer {
-    fn create_shader(&self, vertex: &str, fragment: &str) -> Result<Shader, ()> {
+    fn create_shader(&self, vertex: &str, fragment: &str) -> Result<Shader, RenderError> {
         console_log!("Creating shader...");
-        let program = Shader::new(&self.gl, vertex, fragment).or(Err(()))?;
-        Ok(program)
+        self.create_shader_with_defines(vertex, fragment, &[])
+    }
+
+    fn create_shader_with_layout(&self, vertex: &str, fragment: &str, layout: &VertexLayout) -> Result<Shader, RenderError> {
+        let (vertex, fragment) = self.preprocessor.borrow().process_program(vertex, fragment, &[], self.backend())?;
+        Shader::from_sources(&self.gl, &vertex, &fragment, layout)
+    }
+
+    fn create_shader_with_defines(&self, vertex: &str, fragment: &str, defines: &[(&str, &str)]) -> Result<Shader, RenderError> {
+        let (vertex, fragment) = self.preprocessor.borrow().process_program(vertex, fragment, defines, self.backend())?;
+        Ok(Shader::from_sources(&self.gl, &vertex, &fragment, &VertexLayout::standard())?.with_defines(defines))
+    }
+
+    fn get_preprocessor(&self) -> &RefCell<Preprocessor> {
+        &self.preprocessor
+    }
+
+    fn reload_shader(&self, shader: &Shader, vertex: &str, fragment: &str) -> Result<(), RenderError> {
+        let (vertex, fragment) = self.preprocessor.borrow().process_program(vertex, fragment, &shader.get_defines(), self.backend())?;
+        shader.reload(&vertex, &fragment)
     }
 
     fn set_shader(&self, program: Option<&Shader>) {
-        if program.is_some() {
-            self.gl.use_program(Some(&(program.unwrap().program)));
+        if let Some(program) = program {
+            self.gl.use_program(program.program().as_ref());
+            *self.attributes.borrow_mut() = program.attribute_locations();
+            self.globals.get().apply(program);
         }
     }
 
+    fn set_globals(&self, globals: GlobalUniforms) {
+        self.globals.set(globals);
+    }
+
+    fn get_globals(&self) -> GlobalUniforms {
+        self.globals.get()
+    }
+
     fn draw_mesh(&self, mesh: &Mesh) {
         self.draw_mesh_mode(mesh, mesh.draw_mode);
     }
 
     fn draw_mesh_mode(&self, mesh: &Mesh, draw_mode: u32) {
-        self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, mesh.get_vertex_buffer());
-        self.gl.enable_vertex_attrib_array(POSITION_ATTRIBUTE);
-        self.gl.vertex_attrib_pointer_with_i32(POSITION_ATTRIBUTE, 3, WebGlRenderingContext::FLOAT, false, 0, 0);
-
-        if (mesh.using_normals()) {
-            self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, mesh.get_normal_buffer());
-            self.gl.enable_vertex_attrib_array(NORMAL_ATTRIBUTE);
-            self.gl.vertex_attrib_pointer_with_i32(NORMAL_ATTRIBUTE, 3, WebGlRenderingContext::FLOAT, false, 0, 0);
-        } else {
-            self.gl.disable_vertex_attrib_array(NORMAL_ATTRIBUTE);
+        self.bind_mesh(mesh);
+        self.draw_bound(mesh, draw_mode);
+        self.unbind_mesh();
+    }
+
+    fn draw_mesh_instanced(&self, mesh: &Mesh, instances: &InstanceBuffer) {
+        if instances.is_empty() {
+            return;
         }
 
-        if (mesh.using_colors()) {
-            self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, mesh.get_colors_buffer());
-            self.gl.enable_vertex_attrib_array(COLOR_ATTRIBUTE);
-            self.gl.vertex_attrib_pointer_with_i32(COLOR_ATTRIBUTE, 4, WebGlRenderingContext::FLOAT, false, 0, 0);
-        } else {
-            self.gl.disable_vertex_attrib_array(COLOR_ATTRIBUTE);
+        if !self.supports_instancing() {
+            self.draw_instances_individually(mesh, instances);
+            return;
         }
 
-        if (mesh.using_texcoords()) {
-            self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, mesh.get_texcoord_buffer());
-            self.gl.enable_vertex_attrib_array(TEXCOORD_ATTRIBUTE);
-            self.gl.vertex_attrib_pointer_with_i32(TEXCOORD_ATTRIBUTE, 2, WebGlRenderingContext::FLOAT, false, 0, 0);
-        } else {
-            self.gl.disable_vertex_attrib_array(TEXCOORD_ATTRIBUTE);
+        self.bind_mesh(mesh);
+        let slots = self.bind_instance_attributes(instances);
+
+        let count = instances.len() as i32;
+        if mesh.using_indicies() {
+            self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, mesh.get_index_buffer());
+        }
+        match (&self.gl2, &self.instancing) {
+            (Some(gl2), _) if mesh.using_indicies() => {
+                gl2.draw_elements_instanced_with_i32(mesh.draw_mode, mesh.num_indicies(), mesh.get_index_type(), 0, count);
+            }
+            (Some(gl2), _) => gl2.draw_arrays_instanced(mesh.draw_mode, 0, mesh.num_verticies(), count),
+            (None, Some(angle)) if mesh.using_indicies() => {
+                angle.draw_elements_instanced_angle_with_i32(mesh.draw_mode, mesh.num_indicies(), mesh.get_index_type(), 0, count);
+            }
+            (None, Some(angle)) => angle.draw_arrays_instanced_angle(mesh.draw_mode, 0, mesh.num_verticies(), count),
+            (None, None) => {}
         }
 
-        self.gl.draw_arrays(
-            draw_mode,
-            0,
-            mesh.num_verticies(),
-        );
+        // Put the slots back to per vertex arrays so plain draws are unaffected
+        for location in slots {
+            self.set_divisor(location, 0);
+            self.gl.disable_vertex_attrib_array(location);
+        }
+        self.unbind_mesh();
     }
 
-    fn create_buffer(&self) -> Result<WebGlBuffer, ()> {
-        self.gl.create_buffer().ok_or(())
+    fn supports_instancing(&self) -> bool {
+        self.gl2.is_some() || self.instancing.is_some()
+    }
+
+    fn draw_scene(&self, scene: &Scene) {
+        scene.draw(self);
+    }
+
+    fn create_buffer(&self) -> Result<GpuBuffer, RenderError> {
+        GpuBuffer::new(&self.gl)
+    }
+
+    fn supports_uint_indicies(&self) -> bool {
+        self.uint_indicies
+    }
+
+    fn create_texture(&self, width: u32, height: u32, data: &[u8], options: &TextureOptions) -> Result<Texture, RenderError> {
+        Texture::from_rgba(&self.gl, width, height, data, options).map_err(|err| RenderError::Texture(RenderError::js_message(&err)))
+    }
+
+    fn create_texture_from_image(&self, image: &HtmlImageElement, options: &TextureOptions) -> Result<Texture, RenderError> {
+        Texture::from_image(&self.gl, image, options).map_err(|err| RenderError::Texture(RenderError::js_message(&err)))
+    }
+
+    fn create_texture_from_bitmap(&self, bitmap: &ImageBitmap, options: &TextureOptions) -> Result<Texture, RenderError> {
+        Texture::from_bitmap(&self.gl, bitmap, options).map_err(|err| RenderError::Texture(RenderError::js_message(&err)))
+    }
+
+    fn create_render_target(&self, width: u32, height: u32, options: &RenderTargetOptions) -> Result<RenderTarget, RenderError> {
+        for extension in options.format.required_extensions(self.gl2.is_some()) {
+            if !enable_extension(&self.gl, extension) {
+                return Err(RenderError::Unsupported(format!("rendering to {:?} needs {}", options.format, extension)));
+            }
+        }
+        if options.depth && options.depth_texture && self.gl2.is_none() && !enable_extension(&self.gl, "WEBGL_depth_texture") {
+            return Err(RenderError::Unsupported(String::from("depth textures need WEBGL_depth_texture")));
+        }
+
+        let target = RenderTarget::new_gl(&self.gl, width, height, options);
+        // Creating the framebuffer unbinds whatever was being drawn into
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, self.target.borrow().as_ref().map(|(framebuffer, ..)| framebuffer));
+        target.map_err(|err| RenderError::RenderTarget(RenderError::js_message(&err)))
+    }
+
+    fn set_render_target(&self, target: Option<&RenderTarget>) {
+        let framebuffer = target.and_then(|target| target.framebuffer());
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, framebuffer);
+        *self.target.borrow_mut() = match (target, framebuffer) {
+            (Some(target), Some(framebuffer)) => Some((framebuffer.clone(), target.get_width() as i32, target.get_height() as i32)),
+            _ => None,
+        };
+        self.reset_viewport();
+    }
+
+    fn read_pixels(&self, x: i32, y: i32, width: i32, height: i32) -> Result<Vec<u8>, RenderError> {
+        let mut pixels = vec![0; (width.max(0) * height.max(0) * 4) as usize];
+        self.gl.read_pixels_with_opt_u8_array(
+            x,
+            y,
+            width,
+            height,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        ).map_err(|err| RenderError::RenderTarget(RenderError::js_message(&err)))?;
+        Ok(pixels)
     }
 
     fn clear(&self, color: Vector4<f32>) {
         self.gl.clear_color(color[0], color[1], color[2], color[3]);
-        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT);
     }
 
     fn begin_render(&self) {
-        if self.canvas.is_some() {
-            self.gl.viewport(0, 0, self.get_width(), self.get_height());
-        }
+        self.reset_viewport();
     }
 
     fn end_render(&self) {
 
     }
 
+    fn enable_depth_test(&self) {
+        self.gl.enable(WebGlRenderingContext::DEPTH_TEST);
+    }
+
+    fn disable_depth_test(&self) {
+        self.gl.disable(WebGlRenderingContext::DEPTH_TEST);
+    }
+
     fn get_width(&self) -> i32 {
         self.canvas.as_ref().unwrap().width().try_into().unwrap()
     }
@@ -133,23 +302,278 @@ impl Renderer for GlRenderer {
 
 impl GlRenderer {
     pub fn new(gl: WebGlRenderingContext) -> GlRenderer {
-        GlRenderer { gl, canvas: None }
+        // A WebGL 2 context can be handed in through its WebGL 1 type, pick it back up if so
+        let gl2 = gl.dyn_ref::<WebGl2RenderingContext>().cloned();
+        GlRenderer::with_context(gl, gl2, None)
+    }
+
+    pub fn new_webgl2(gl2: WebGl2RenderingContext) -> GlRenderer {
+        GlRenderer::with_context(gl2.clone().unchecked_into(), Some(gl2), None)
     }
 
-    pub fn create(canvas: web_sys::HtmlCanvasElement) -> Result<GlRenderer, JsValue> {
+    /// Render to a canvas through WebGL 2, falling back to WebGL 1 where it is unavailable.
+    pub fn create(canvas: web_sys::HtmlCanvasElement) -> Result<GlRenderer, RenderError> {
         console_log!("Creating GlRenderer for canvas.");
+        let context = canvas.get_context("webgl2").map_err(|err| RenderError::ContextUnavailable(RenderError::js_message(&err)))?;
+        if let Some(context) = context {
+            let gl2 = context
+                .dyn_into::<WebGl2RenderingContext>()
+                .map_err(|_| RenderError::ContextUnavailable(String::from("Canvas returned an unexpected webgl2 context")))?;
+            console_log!("Using WebGL 2.");
+            return Ok(GlRenderer::with_context(gl2.clone().unchecked_into(), Some(gl2), Some(canvas)));
+        }
+
+        GlRenderer::create_webgl1(canvas)
+    }
+
+    /// Render to a canvas through WebGL 1 even if WebGL 2 is available.
+    pub fn create_webgl1(canvas: web_sys::HtmlCanvasElement) -> Result<GlRenderer, RenderError> {
         let gl = canvas
-            .get_context("webgl")?
-            .unwrap()
-            .dyn_into::<WebGlRenderingContext>()?;
-        Ok(GlRenderer{gl, canvas: Some(canvas)})
+            .get_context("webgl")
+            .map_err(|err| RenderError::ContextUnavailable(RenderError::js_message(&err)))?
+            .ok_or_else(|| RenderError::ContextUnavailable(String::from("Unable to get a WebGL context")))?
+            .dyn_into::<WebGlRenderingContext>()
+            .map_err(|_| RenderError::ContextUnavailable(String::from("Canvas returned an unexpected webgl context")))?;
+        console_log!("Using WebGL 1.");
+        Ok(GlRenderer::with_context(gl, None, Some(canvas)))
     }
 
-    pub fn enable_depth_test(&self) {
-        self.gl.enable(WebGlRenderingContext::DEPTH_TEST);
+    fn with_context(gl: WebGlRenderingContext, gl2: Option<WebGl2RenderingContext>, canvas: Option<web_sys::HtmlCanvasElement>) -> GlRenderer {
+        // 32-bit indicies are core in WebGL 2
+        let uint_indicies = gl2.is_some() || enable_extension(&gl, "OES_element_index_uint");
+        // Instancing is core in WebGL 2 as well
+        let instancing = match gl2 {
+            Some(_) => None,
+            // Extension objects have no global constructor to check against, so cast without checking
+            None => gl.get_extension("ANGLE_instanced_arrays").ok().flatten().map(|ext| ext.unchecked_into()),
+        };
+        GlRenderer {
+            gl,
+            gl2,
+            instancing,
+            canvas,
+            uint_indicies,
+            attributes: RefCell::new(Rc::new(HashMap::new())),
+            enabled_attributes: Cell::new(0),
+            target: RefCell::new(None),
+            preprocessor: RefCell::new(Preprocessor::new()),
+            globals: Cell::new(GlobalUniforms::new()),
+        }
     }
 
-    pub fn disable_depth_test(&self) {
-        self.gl.disable(WebGlRenderingContext::DEPTH_TEST);
+    pub fn backend(&self) -> GlBackend {
+        if self.gl2.is_some() { GlBackend::WebGl2 } else { GlBackend::WebGl1 }
+    }
+
+    pub fn get_gl2(&self) -> Option<&WebGl2RenderingContext> {
+        self.gl2.as_ref()
+    }
+
+    pub fn create_uniform_buffer(&self, size: i32) -> Result<UniformBuffer, RenderError> {
+        let gl2 = self.gl2.as_ref().ok_or_else(|| RenderError::Unsupported(String::from("uniform buffers need WebGL 2")))?;
+        UniformBuffer::new(gl2, size)
+    }
+
+    /// Route fragment shader outputs to the first `count` color attachments of the bound framebuffer,
+    /// WebGL 2 only.
+    pub fn set_draw_buffers(&self, count: u32) -> Result<(), RenderError> {
+        let gl2 = self.gl2.as_ref().ok_or_else(|| RenderError::Unsupported(String::from("multiple draw buffers need WebGL 2")))?;
+        if count > self.max_draw_buffers() {
+            return Err(RenderError::Unsupported(format!("drawing to {} buffers, at most {} are available", count, self.max_draw_buffers())));
+        }
+
+        let attachments = js_sys::Array::new();
+        for i in 0..count {
+            attachments.push(&JsValue::from(WebGl2RenderingContext::COLOR_ATTACHMENT0 + i));
+        }
+        gl2.draw_buffers(&attachments);
+        Ok(())
+    }
+
+    /// How many color attachments can be drawn to at once, one without WebGL 2.
+    pub fn max_draw_buffers(&self) -> u32 {
+        match &self.gl2 {
+            Some(gl2) => gl2.get_parameter(WebGl2RenderingContext::MAX_DRAW_BUFFERS)
+                .ok()
+                .and_then(|v| v.as_f64())
+                .unwrap_or(1.0) as u32,
+            None => 1,
+        }
+    }
+
+    /// Cover the whole of the render target being drawn into, or the canvas.
+    fn reset_viewport(&self) {
+        match self.target.borrow().as_ref() {
+            Some((_, width, height)) => self.gl.viewport(0, 0, *width, *height),
+            None if self.canvas.is_some() => self.gl.viewport(0, 0, self.get_width(), self.get_height()),
+            None => {}
+        }
+    }
+
+    /// Point the bound shader's attributes at the mesh's buffers.
+    fn bind_mesh(&self, mesh: &Mesh) {
+        match &self.gl2 {
+            Some(gl2) => self.bind_vertex_array(gl2, mesh),
+            None => {
+                let enabled = bind_attributes(&self.gl, mesh, &self.attributes.borrow());
+
+                // Arrays left enabled by an earlier mesh would read past the end of this mesh's buffers
+                let stale = self.enabled_attributes.get() & !enabled;
+                for location in (0..32).filter(|l| stale & (1 << l) != 0) {
+                    self.gl.disable_vertex_attrib_array(location);
+                }
+                self.enabled_attributes.set(enabled);
+            }
+        }
+    }
+
+    fn unbind_mesh(&self) {
+        // Keep later buffer binds from being recorded into the mesh's vertex array
+        if let Some(gl2) = &self.gl2 {
+            gl2.bind_vertex_array(None);
+        }
+    }
+
+    /// Draw a mesh whose attributes are already bound.
+    fn draw_bound(&self, mesh: &Mesh, draw_mode: u32) {
+        if mesh.using_indicies() {
+            self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, mesh.get_index_buffer());
+            self.gl.draw_elements_with_i32(
+                draw_mode,
+                mesh.num_indicies(),
+                mesh.get_index_type(),
+                0,
+            );
+        } else {
+            self.gl.draw_arrays(
+                draw_mode,
+                0,
+                mesh.num_verticies(),
+            );
+        }
+    }
+
+    /// Feed the instance attributes the bound shader uses one value per instance.
+    /// Returns the attribute locations used.
+    fn bind_instance_attributes(&self, instances: &InstanceBuffer) -> Vec<u32> {
+        let locations = self.attributes.borrow();
+        let mut slots = Vec::new();
+
+        self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, instances.get_buffer());
+        for attribute in instances.get_layout().get_attributes() {
+            let base = match locations.get(&attribute.name) {
+                Some(location) => *location,
+                None => continue,
+            };
+
+            for (column, size, offset) in attribute_columns(attribute) {
+                let location = base + column;
+                self.gl.enable_vertex_attrib_array(location);
+                self.gl.vertex_attrib_pointer_with_i32(location, size, WebGlRenderingContext::FLOAT, false, attribute.stride, offset);
+                self.set_divisor(location, 1);
+                slots.push(location);
+            }
+        }
+
+        slots
+    }
+
+    fn set_divisor(&self, location: u32, divisor: u32) {
+        match (&self.gl2, &self.instancing) {
+            (Some(gl2), _) => gl2.vertex_attrib_divisor(location, divisor),
+            (None, Some(angle)) => angle.vertex_attrib_divisor_angle(location, divisor),
+            (None, None) => {}
+        }
+    }
+
+    /// Without instancing support, draw once per instance with its values set as constant attributes.
+    fn draw_instances_individually(&self, mesh: &Mesh, instances: &InstanceBuffer) {
+        self.bind_mesh(mesh);
+        let locations = self.attributes.borrow();
+
+        for instance in 0..instances.len() {
+            for attribute in instances.get_layout().get_attributes() {
+                let (base, values) = match (locations.get(&attribute.name), instances.get_value(instance, &attribute.name)) {
+                    (Some(base), Some(values)) => (*base, values),
+                    _ => continue,
+                };
+
+                for (column, size, _) in attribute_columns(attribute) {
+                    let location = base + column;
+                    let start = (column as i32 * size) as usize;
+                    let values = &values[start..start + size as usize];
+
+                    // A disabled attribute array reads the same constant value for every vertex
+                    self.gl.disable_vertex_attrib_array(location);
+                    match size {
+                        1 => self.gl.vertex_attrib1fv_with_f32_array(location, values),
+                        2 => self.gl.vertex_attrib2fv_with_f32_array(location, values),
+                        3 => self.gl.vertex_attrib3fv_with_f32_array(location, values),
+                        _ => self.gl.vertex_attrib4fv_with_f32_array(location, values),
+                    }
+                }
+            }
+
+            self.draw_bound(mesh, mesh.draw_mode);
+        }
+
+        self.unbind_mesh();
     }
+
+    /// Bind the mesh's vertex array, recording a new one if the bound shader's attributes changed.
+    fn bind_vertex_array(&self, gl2: &WebGl2RenderingContext, mesh: &Mesh) {
+        let locations = self.attributes.borrow();
+        let mut cached = mesh.vertex_array().borrow_mut();
+
+        if let Some(vertex_array) = cached.as_ref().filter(|va| va.matches(&locations)) {
+            vertex_array.bind();
+            return;
+        }
+
+        *cached = None;
+        let vertex_array = match VertexArray::new(gl2, locations.clone()) {
+            Ok(vertex_array) => vertex_array,
+            Err(_) => {
+                console_log!("Unable to create vertex array!");
+                return;
+            }
+        };
+
+        vertex_array.bind();
+        bind_attributes(&self.gl, mesh, &locations);
+        *cached = Some(vertex_array);
+    }
+}
+
+/// Point each of the mesh's attributes the bound shader uses at its buffer.
+/// Returns a bitmask of the attribute locations enabled.
+fn bind_attributes(gl: &WebGlRenderingContext, mesh: &Mesh, locations: &HashMap<String, u32>) -> u32 {
+    // Attributes are matched to the bound shader by name, so the mesh and shader layouts
+    // only need to agree on names rather than slots.
+    let mut enabled = 0u32;
+    for attribute in mesh.get_layout().get_attributes() {
+        let location = match locations.get(&attribute.name) {
+            Some(location) => *location,
+            None => continue,
+        };
+
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, mesh.get_buffer(attribute.buffer));
+        gl.enable_vertex_attrib_array(location);
+        gl.vertex_attrib_pointer_with_i32(
+            location,
+            attribute.components,
+            attribute.ty.gl_enum(),
+            attribute.normalized,
+            attribute.stride,
+            attribute.offset,
+        );
+        enabled |= 1 << location;
+    }
+
+    enabled
+}
+
+/// Extensions in WebGL are enabled by requesting them, returns whether it is available.
+fn enable_extension(gl: &WebGlRenderingContext, name: &str) -> bool {
+    matches!(gl.get_extension(name), Ok(Some(_)))
 }
@@ -0,0 +1,173 @@
+use std::rc::Rc;
+use nalgebra::{ vector, Vector2, Vector4 };
+use web_sys::WebGlRenderingContext;
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::material::{Material, MaterialParam};
+use crate::render::mesh::Mesh;
+use crate::render::target::{RenderTarget, RenderTargetOptions};
+use crate::shader::{
+    SHADER_POST_VERT, SHADER_COPY_FRAG, SHADER_BLOOM_FRAG, SHADER_VIGNETTE_FRAG, SHADER_GRAIN_FRAG, SHADER_FXAA_FRAG,
+};
+use crate::log;
+
+macro_rules! console_log { ($($t:tt)*) => (log(&format!("[post] {}", &format_args!($($t)*)).to_string())) }
+
+/// A full screen pass: a fragment shader reading the previous pass through the `source` sampler,
+/// plus the parameters in its material. `resolution` is set to the size of `source` in pixels.
+pub struct PostEffect {
+    material: Material,
+    pub enabled: bool,
+}
+
+impl PostEffect {
+    /// Compile an effect from a fragment shader, which reads `frag_texcoord` from `post.v.glsl`.
+    pub fn new(render: &dyn Renderer, fragment: &str) -> Result<PostEffect, RenderError> {
+        let shader = render.create_shader(SHADER_POST_VERT, fragment)?;
+        Ok(PostEffect { material: Material::new(Rc::new(shader)), enabled: true })
+    }
+
+    pub fn copy(render: &dyn Renderer) -> Result<PostEffect, RenderError> {
+        PostEffect::new(render, SHADER_COPY_FRAG)
+    }
+
+    /// Glow around anything brighter than `bloomThreshold`.
+    pub fn bloom(render: &dyn Renderer) -> Result<PostEffect, RenderError> {
+        let mut effect = PostEffect::new(render, SHADER_BLOOM_FRAG)?;
+        effect.material.set_float("bloomThreshold", 0.3);
+        effect.material.set_float("bloomIntensity", 1.5);
+        effect.material.set_float("bloomRadius", 6.0);
+        Ok(effect)
+    }
+
+    pub fn vignette(render: &dyn Renderer) -> Result<PostEffect, RenderError> {
+        let mut effect = PostEffect::new(render, SHADER_VIGNETTE_FRAG)?;
+        effect.material.set_float("vignetteStrength", 0.5);
+        effect.material.set_float("vignetteRadius", 0.4);
+        Ok(effect)
+    }
+
+    /// Noise that changes with the global `time`, see `GlobalUniforms`.
+    pub fn film_grain(render: &dyn Renderer) -> Result<PostEffect, RenderError> {
+        let mut effect = PostEffect::new(render, SHADER_GRAIN_FRAG)?;
+        effect.material.set_float("grainAmount", 0.05);
+        Ok(effect)
+    }
+
+    pub fn fxaa(render: &dyn Renderer) -> Result<PostEffect, RenderError> {
+        PostEffect::new(render, SHADER_FXAA_FRAG)
+    }
+
+    pub fn get_material(&self) -> &Material { &self.material }
+    pub fn get_material_mut(&mut self) -> &mut Material { &mut self.material }
+
+    /// Draw `quad` with this effect, reading from `source`.
+    fn apply(&self, render: &dyn Renderer, source: &RenderTarget, quad: &Mesh) {
+        // Unknown parameters are not an error, effects only declare the uniforms they use
+        self.material.apply(render).ok();
+
+        let shader = self.material.get_shader();
+        let unit = self.material.get_params().values().filter(|p| matches!(p, MaterialParam::Texture(_))).count();
+        shader.set_texture("source", source.get_texture(), unit as u32).ok();
+        if shader.has_uniform("resolution") {
+            shader.set_uniform2f("resolution", Vector2::new(source.get_width() as f32, source.get_height() as f32)).ok();
+        }
+
+        render.draw_mesh(quad);
+    }
+}
+
+/// Renders the scene into an offscreen target, then runs each enabled effect in order,
+/// bouncing between two targets and finishing on the canvas.
+///
+/// ```ignore
+/// post.update_size(&render);
+/// post.begin(&render);
+/// // draw the scene as usual
+/// post.end(&render);
+/// ```
+pub struct PostChain {
+    effects: Vec<PostEffect>,
+    /// The scene is drawn into the first, which is the only one with depth.
+    targets: [RenderTarget; 2],
+    quad: Mesh,
+    /// Used when every effect is disabled, to still get the scene onto the canvas.
+    copy: PostEffect,
+}
+
+impl PostChain {
+    /// The targets follow the canvas size, multiplied by `canvas_scale` if set in `options`.
+    pub fn new(render: &dyn Renderer, options: &RenderTargetOptions) -> Result<PostChain, RenderError> {
+        let scene_options = RenderTargetOptions { canvas_scale: Some(options.canvas_scale.unwrap_or(1.0)), ..*options };
+        let swap_options = RenderTargetOptions { depth: false, ..scene_options };
+
+        let mut targets = [
+            render.create_render_target(1, 1, &scene_options)?,
+            render.create_render_target(1, 1, &swap_options)?,
+        ];
+        for target in targets.iter_mut() {
+            target.update_size(render).map_err(|err| RenderError::RenderTarget(RenderError::js_message(&err)))?;
+        }
+
+        let mut quad = Mesh::new();
+        quad.add_verticies(vec![vector!(-1.0, -1.0, 0.0), vector!(1.0, -1.0, 0.0), vector!(-1.0, 1.0, 0.0), vector!(1.0, 1.0, 0.0)]);
+        quad.add_texcoords(vec![vector!(0.0, 0.0), vector!(1.0, 0.0), vector!(0.0, 1.0), vector!(1.0, 1.0)]);
+        quad.use_texcoords = true;
+        quad.draw_mode = WebGlRenderingContext::TRIANGLE_STRIP;
+        quad.update_buffers(render)?;
+
+        Ok(PostChain { effects: Vec::new(), targets, quad, copy: PostEffect::copy(render)? })
+    }
+
+    pub fn push(&mut self, effect: PostEffect) {
+        self.effects.push(effect);
+    }
+
+    pub fn insert(&mut self, index: usize, effect: PostEffect) {
+        self.effects.insert(index, effect);
+    }
+
+    pub fn remove(&mut self, index: usize) -> PostEffect {
+        self.effects.remove(index)
+    }
+
+    pub fn get_effects(&self) -> &Vec<PostEffect> { &self.effects }
+    pub fn get_effects_mut(&mut self) -> &mut Vec<PostEffect> { &mut self.effects }
+
+    /// The target the scene is drawn into, which holds the unprocessed frame until `end`.
+    pub fn get_scene_target(&self) -> &RenderTarget { &self.targets[0] }
+
+    /// Follow the canvas size, call once a frame before `begin`.
+    pub fn update_size(&mut self, render: &dyn Renderer) {
+        for target in self.targets.iter_mut() {
+            if target.update_size(render).is_err() {
+                console_log!("Unable to resize post processing target!");
+            }
+        }
+    }
+
+    /// Start drawing the scene into the offscreen target.
+    pub fn begin(&self, render: &dyn Renderer) {
+        render.set_render_target(Some(&self.targets[0]));
+    }
+
+    /// Run every enabled effect and draw the result onto the canvas, which is left bound.
+    pub fn end(&self, render: &dyn Renderer) {
+        let mut effects: Vec<&PostEffect> = self.effects.iter().filter(|e| e.enabled).collect();
+        if effects.is_empty() {
+            effects.push(&self.copy);
+        }
+
+        let mut source = 0;
+        for (i, effect) in effects.iter().enumerate() {
+            let last = i + 1 == effects.len();
+            // The scene target is free again once the first effect has read it
+            let destination = if last { None } else { Some(&self.targets[1 - source]) };
+
+            render.set_render_target(destination);
+            render.clear(Vector4::zeros());
+            effect.apply(render, &self.targets[source], &self.quad);
+            source = 1 - source;
+        }
+    }
+}
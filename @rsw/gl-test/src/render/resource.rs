@@ -0,0 +1,176 @@
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use web_sys::{WebGlBuffer, WebGlRenderingContext};
+use crate::render::error::RenderError;
+
+/// The kinds of GPU object counted by `ResourceCounts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Buffer,
+    Program,
+    Texture,
+    Framebuffer,
+    Renderbuffer,
+    VertexArray,
+}
+
+static LIVE: [AtomicUsize; 6] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Record a GPU object being created, to be matched by `deleted` when it is freed.
+pub(crate) fn created(kind: ResourceKind) {
+    LIVE[kind as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn deleted(kind: ResourceKind) {
+    LIVE[kind as usize].fetch_sub(1, Ordering::Relaxed);
+}
+
+/// How many GPU objects of each kind are currently alive. Every handle frees its object when
+/// dropped, so a count that keeps growing from frame to frame points at something being leaked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceCounts {
+    pub buffers: usize,
+    pub programs: usize,
+    pub textures: usize,
+    pub framebuffers: usize,
+    pub renderbuffers: usize,
+    pub vertex_arrays: usize,
+}
+
+impl ResourceCounts {
+    pub fn current() -> ResourceCounts {
+        let live = |kind: ResourceKind| LIVE[kind as usize].load(Ordering::Relaxed);
+        ResourceCounts {
+            buffers: live(ResourceKind::Buffer),
+            programs: live(ResourceKind::Program),
+            textures: live(ResourceKind::Texture),
+            framebuffers: live(ResourceKind::Framebuffer),
+            renderbuffers: live(ResourceKind::Renderbuffer),
+            vertex_arrays: live(ResourceKind::VertexArray),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.buffers + self.programs + self.textures + self.framebuffers + self.renderbuffers + self.vertex_arrays
+    }
+}
+
+impl fmt::Display for ResourceCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} buffers, {} programs, {} textures, {} framebuffers, {} renderbuffers, {} vertex arrays",
+            self.buffers, self.programs, self.textures, self.framebuffers, self.renderbuffers, self.vertex_arrays,
+        )
+    }
+}
+
+/// How often a buffer's contents are expected to change, passed on to GL as a hint for where to keep it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferUsage {
+    /// Uploaded once and drawn many times.
+    #[default]
+    Static,
+    /// Changed now and then, such as a mesh animated on the CPU.
+    Dynamic,
+    /// Replaced about every time it is drawn.
+    Stream,
+}
+
+impl BufferUsage {
+    pub fn gl_enum(&self) -> u32 {
+        match self {
+            BufferUsage::Static => WebGlRenderingContext::STATIC_DRAW,
+            BufferUsage::Dynamic => WebGlRenderingContext::DYNAMIC_DRAW,
+            BufferUsage::Stream => WebGlRenderingContext::STREAM_DRAW,
+        }
+    }
+}
+
+/// A GL buffer that is deleted when dropped. Derefs to the `WebGlBuffer` for binding.
+/// Storage is allocated with `reserve` and filled with `write`, so it is only reallocated when it has to grow.
+#[derive(Debug)]
+pub struct GpuBuffer {
+    buffer: WebGlBuffer,
+    gl: WebGlRenderingContext,
+    /// Bytes of storage allocated so far.
+    size: i32,
+    usage: BufferUsage,
+}
+
+impl GpuBuffer {
+    pub fn new(gl: &WebGlRenderingContext) -> Result<GpuBuffer, RenderError> {
+        let buffer = gl.create_buffer().ok_or_else(|| RenderError::BufferAllocation(String::from("vertex buffer")))?;
+        created(ResourceKind::Buffer);
+        Ok(GpuBuffer { buffer, gl: gl.clone(), size: 0, usage: BufferUsage::default() })
+    }
+
+    pub fn get_size(&self) -> i32 { self.size }
+    pub fn get_usage(&self) -> BufferUsage { self.usage }
+
+    /// Bind the buffer to `target` and make sure it holds at least `size` bytes for `usage`.
+    /// Returns whether the storage was reallocated, which discards everything written to it.
+    pub fn reserve(&mut self, target: u32, size: i32, usage: BufferUsage) -> bool {
+        self.gl.bind_buffer(target, Some(&self.buffer));
+        if size <= self.size && usage == self.usage {
+            return false;
+        }
+
+        self.gl.buffer_data_with_i32(target, size, usage.gl_enum());
+        self.size = size;
+        self.usage = usage;
+        true
+    }
+
+    /// Write a typed array `offset` bytes into the buffer, which has to be large enough to hold it.
+    pub fn write(&self, target: u32, offset: i32, data: &js_sys::Object) {
+        self.gl.bind_buffer(target, Some(&self.buffer));
+        self.gl.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, data);
+    }
+
+    /// Write floats `offset` bytes into the buffer straight out of wasm memory, in one call
+    /// rather than copying them into a JS array first.
+    pub fn write_floats(&self, target: u32, offset: i32, data: &[f32]) {
+        self.gl.bind_buffer(target, Some(&self.buffer));
+        // SAFETY: the view aliases wasm memory, which is invalidated if the memory grows.
+        // Nothing allocates between creating it and GL copying out of it, and it is dropped right after.
+        unsafe {
+            let view = js_sys::Float32Array::view(data);
+            self.gl.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, &view);
+        }
+    }
+
+    /// Write bytes `offset` bytes into the buffer, handed over as a view of wasm memory like `write_floats`.
+    pub fn write_bytes(&self, target: u32, offset: i32, data: &[u8]) {
+        self.gl.bind_buffer(target, Some(&self.buffer));
+        // SAFETY: the view aliases wasm memory, which is invalidated if the memory grows.
+        // Nothing allocates between creating it and GL copying out of it, and it is dropped right after.
+        unsafe {
+            let view = js_sys::Uint8Array::view(data);
+            self.gl.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, &view);
+        }
+    }
+}
+
+impl Deref for GpuBuffer {
+    type Target = WebGlBuffer;
+
+    fn deref(&self) -> &WebGlBuffer {
+        &self.buffer
+    }
+}
+
+impl Drop for GpuBuffer {
+    fn drop(&mut self) {
+        self.gl.delete_buffer(Some(&self.buffer));
+        deleted(ResourceKind::Buffer);
+    }
+}
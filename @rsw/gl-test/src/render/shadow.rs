@@ -0,0 +1,223 @@
+use nalgebra::{ Matrix4, Point3, Vector3, Vector4 };
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::light::{LightSet, LightSource};
+use crate::render::mesh::Mesh;
+use crate::render::target::{ColorFormat, RenderTarget, RenderTargetOptions};
+use crate::render::texture::TextureFilter;
+use crate::shader::{Shader, SHADER_SHADOW_VERT, SHADER_SHADOW_FRAG};
+
+/// The most shadow casting lights drawn at once, defined as `MAX_SHADOWS` for shaders by the `Preprocessor`.
+pub const MAX_SHADOWS: usize = 2;
+
+/// Shadow maps take the last of the eight texture units WebGL guarantees, leaving the rest to materials.
+pub const FIRST_SHADOW_UNIT: u32 = 8 - MAX_SHADOWS as u32;
+
+/// The most texels the built-in shaders average over in each direction, see `ShadowSettings::pcf_radius`.
+pub const MAX_PCF_RADIUS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    /// Depth subtracted before comparing against the map, to keep surfaces from shadowing themselves.
+    pub bias: f32,
+    /// Extra bias for surfaces at grazing angles to the light, scaled by the tangent of the angle.
+    pub slope_bias: f32,
+    /// Texels averaged in each direction around a lookup to soften the edges, zero for hard shadows.
+    pub pcf_radius: u32,
+    /// Directional lights cover a box reaching this far each way from `center`.
+    pub extent: f32,
+    pub center: Vector3<f32>,
+    /// Depth range of a spot light's shadow. `far` is replaced by the light's range when it has one.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            resolution: 1024,
+            bias: 0.002,
+            slope_bias: 0.002,
+            pcf_radius: 1,
+            extent: 10.0,
+            center: Vector3::zeros(),
+            near: 0.05,
+            far: 50.0,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// Transform from world space into the clip space of a light's shadow map.
+    pub fn light_matrix(&self, light: &LightSource) -> Option<Matrix4<f32>> {
+        match light {
+            LightSource::Directional(light) => {
+                let direction = light.direction.try_normalize(f32::EPSILON)?;
+                let eye = self.center + direction * self.extent;
+                let view = Matrix4::look_at_rh(&Point3::from(eye), &Point3::from(self.center), &up_for(direction));
+                let projection = Matrix4::new_orthographic(-self.extent, self.extent, -self.extent, self.extent, 0.0, self.extent * 2.0);
+                Some(projection * view)
+            }
+            LightSource::Spot(light) => {
+                let direction = light.direction.try_normalize(f32::EPSILON)?;
+                let view = Matrix4::look_at_rh(&Point3::from(light.position), &Point3::from(light.position + direction), &up_for(direction));
+                let far = if light.range > 0.0 { light.range } else { self.far };
+                let fov = (light.outer_cone * 2.0).clamp(0.01, std::f32::consts::PI - 0.01);
+                Some(Matrix4::new_perspective(1.0, fov, self.near, far) * view)
+            }
+            LightSource::Point(_) => None,
+        }
+    }
+}
+
+/// An up vector that is not parallel to `direction`.
+fn up_for(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 { Vector3::z() } else { Vector3::y() }
+}
+
+/// A light's shadow map along with what is needed to look it up.
+pub struct ShadowMap {
+    target: RenderTarget,
+    /// Index of the light in the `LightSet` the map was made for.
+    light: usize,
+    matrix: Matrix4<f32>,
+    settings: ShadowSettings,
+}
+
+impl ShadowMap {
+    pub fn get_target(&self) -> &RenderTarget { &self.target }
+    pub fn get_light(&self) -> usize { self.light }
+    pub fn get_matrix(&self) -> Matrix4<f32> { self.matrix }
+    pub fn get_settings(&self) -> &ShadowSettings { &self.settings }
+
+    /// Whether depth is packed into the color texture, for lack of a depth texture.
+    pub fn is_packed(&self) -> bool {
+        self.target.get_depth_texture().is_none()
+    }
+}
+
+/// Renders depth from the point of view of each shadow casting light, for the built-in lit
+/// shaders to compare against. Without depth textures, as on WebGL 1 lacking WEBGL_depth_texture,
+/// depth is packed into the RGBA channels of the color texture instead.
+///
+/// ```ignore
+/// shadows.update(&render, &lights)?; // whenever the lights change
+/// shadows.draw(&render, &casters);
+/// // then for each lit shader, after `lights.apply(&shader)`
+/// shadows.apply(&shader)?;
+/// ```
+pub struct ShadowPass {
+    shader: Shader,
+    maps: Vec<ShadowMap>,
+}
+
+impl ShadowPass {
+    pub fn new(render: &dyn Renderer) -> Result<ShadowPass, RenderError> {
+        let shader = render.create_shader(SHADER_SHADOW_VERT, SHADER_SHADOW_FRAG)?;
+        Ok(ShadowPass { shader, maps: Vec::new() })
+    }
+
+    pub fn get_maps(&self) -> &Vec<ShadowMap> { &self.maps }
+
+    /// Give each shadow casting light in the set a map, up to `MAX_SHADOWS` of them,
+    /// and work out where each light sees from. Maps are reused between calls where possible.
+    pub fn update(&mut self, render: &dyn Renderer, lights: &LightSet) -> Result<(), RenderError> {
+        let mut old = std::mem::take(&mut self.maps).into_iter();
+
+        for (index, light) in lights.get_lights().iter().enumerate() {
+            if self.maps.len() >= MAX_SHADOWS {
+                break;
+            }
+
+            let (settings, matrix) = match light.shadow().and_then(|s| Some((*s, s.light_matrix(light)?))) {
+                Some(shadow) => shadow,
+                None => continue,
+            };
+
+            let resolution = settings.resolution.max(1);
+            let target = match old.next() {
+                Some(map) if map.target.get_width() == resolution => map.target,
+                Some(mut map) => {
+                    map.target.resize(resolution, resolution).map_err(|err| RenderError::RenderTarget(RenderError::js_message(&err)))?;
+                    map.target
+                }
+                None => create_target(render, resolution)?,
+            };
+
+            self.maps.push(ShadowMap { target, light: index, matrix, settings });
+        }
+
+        Ok(())
+    }
+
+    /// Render every map, `casters` being the meshes that cast shadows with their model matrices.
+    /// Leaves the canvas bound, so draw shadows before binding the target the scene goes into.
+    pub fn draw(&self, render: &dyn Renderer, casters: &[(&Mesh, Matrix4<f32>)]) {
+        if self.maps.is_empty() {
+            return;
+        }
+
+        render.set_shader(Some(&self.shader));
+        render.enable_depth_test();
+        for map in &self.maps {
+            render.set_render_target(Some(&map.target));
+            // The far plane, both in the depth buffer and as packed depth
+            render.clear(Vector4::new(1.0, 1.0, 1.0, 1.0));
+
+            for (mesh, model) in casters {
+                self.shader.set_uniform_matrix4f("mvp", map.matrix * model).ok();
+                render.draw_mesh(mesh);
+            }
+        }
+        render.set_render_target(None);
+    }
+
+    /// Upload the shadow uniforms to the bound shader, whose lights must come from the same `LightSet`
+    /// given to `update`. Maps are bound to the texture units from `FIRST_SHADOW_UNIT` up.
+    pub fn apply(&self, shader: &Shader) -> Result<(), RenderError> {
+        shader.set_uniform_bool("receiveShadows", true)?;
+
+        for slot in 0..MAX_SHADOWS {
+            let unit = FIRST_SHADOW_UNIT + slot as u32;
+            let map = match self.maps.get(slot) {
+                Some(map) => map,
+                None => {
+                    shader.set_uniform_sampler(&format!("shadowMap[{}]", slot), unit)?;
+                    shader.set_uniform1i(&format!("shadowLight[{}]", slot), -1)?;
+                    continue;
+                }
+            };
+
+            let settings = &map.settings;
+            let texture = map.target.get_depth_texture().unwrap_or(map.target.get_texture());
+            shader.set_texture(&format!("shadowMap[{}]", slot), texture, unit)?;
+            shader.set_uniform_matrix4f(&format!("shadowMatrix[{}]", slot), map.matrix)?;
+            shader.set_uniform1i(&format!("shadowLight[{}]", slot), map.light as i32)?;
+            shader.set_uniform4f(&format!("shadowParams[{}]", slot), Vector4::new(
+                settings.bias,
+                settings.slope_bias,
+                1.0 / map.target.get_width() as f32,
+                settings.pcf_radius.min(MAX_PCF_RADIUS) as f32,
+            ))?;
+            shader.set_uniform_bool(&format!("shadowPacked[{}]", slot), map.is_packed())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A depth texture target where available, otherwise one to pack depth into.
+fn create_target(render: &dyn Renderer, resolution: u32) -> Result<RenderTarget, RenderError> {
+    let options = RenderTargetOptions {
+        format: ColorFormat::Rgba8,
+        depth: true,
+        depth_texture: true,
+        filter: TextureFilter::Nearest,
+        canvas_scale: None,
+    };
+
+    render.create_render_target(resolution, resolution, &options)
+        .or_else(|_| render.create_render_target(resolution, resolution, &RenderTargetOptions { depth_texture: false, ..options }))
+}
@@ -0,0 +1,1014 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use web_sys::{WebGlRenderingContext, HtmlImageElement, ImageBitmap};
+use nalgebra::{ Vector2, Vector3, Vector4, Matrix4 };
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::gl2::GlBackend;
+use crate::render::globals::GlobalUniforms;
+use crate::render::resource::GpuBuffer;
+use crate::render::mesh::Mesh;
+use crate::render::instance::InstanceBuffer;
+use crate::render::layout::VertexLayout;
+use crate::render::shadow::{MAX_SHADOWS, MAX_PCF_RADIUS};
+use crate::render::target::{RenderTarget, RenderTargetOptions};
+use crate::render::texture::{Texture, TextureOptions, TextureWrap, TextureFilter};
+use crate::scene::Scene;
+use crate::shader::{Shader, UniformData};
+use crate::shader::preprocess::Preprocessor;
+use crate::shader::reflect::Reflection;
+
+/// How the software renderer shades fragments.
+/// GLSL is never executed; instead the closest built-in model is chosen from the
+/// uniforms the fragment source declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftShading {
+    /// Interpolated vertex color, like a pass-through fragment shader.
+    VertexColor,
+    /// The `flatColor` uniform, matching `flatcolor.f.glsl`.
+    FlatColor,
+    /// The `source` sampler read through unchanged, matching `copy.f.glsl`.
+    /// Other post effects have no software equivalent and get this instead.
+    Source,
+    /// Vertex color lit by the ambient light and light array uniforms, matching `simple.f.glsl`.
+    Lit,
+    /// The `diffuseTexture` sampler lit like `Lit`, matching `simple_textured.f.glsl`.
+    Textured,
+    /// Fragment depth packed into RGBA, matching `shadow.f.glsl`.
+    PackedDepth,
+}
+
+impl SoftShading {
+    pub fn detect(fragment: &str) -> SoftShading {
+        if fragment.contains("vec4 packDepth(") {
+            SoftShading::PackedDepth
+        } else if declares_uniform(fragment, "flatColor") {
+            SoftShading::FlatColor
+        } else if declares_uniform(fragment, "source") {
+            SoftShading::Source
+        } else if declares_uniform(fragment, "diffuseTexture") {
+            SoftShading::Textured
+        } else if declares_uniform(fragment, "lightCount") {
+            SoftShading::Lit
+        } else {
+            SoftShading::VertexColor
+        }
+    }
+}
+
+fn declares_uniform(source: &str, name: &str) -> bool {
+    source.lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("uniform "))
+        .any(|line| line.trim_end_matches(';').split_whitespace().last() == Some(name))
+}
+
+/// The software equivalent of a linked program: a shading model plus the uniform values set on it.
+#[derive(Debug)]
+pub struct SoftProgram {
+    pub shading: SoftShading,
+    uniforms: RefCell<HashMap<String, UniformData>>,
+    textures: RefCell<HashMap<u32, Rc<SoftTexture>>>,
+}
+
+impl SoftProgram {
+    pub fn new(shading: SoftShading) -> SoftProgram {
+        SoftProgram { shading, uniforms: RefCell::new(HashMap::new()), textures: RefCell::new(HashMap::new()) }
+    }
+
+    pub fn from_source(_vertex: &str, fragment: &str) -> SoftProgram {
+        SoftProgram::new(SoftShading::detect(fragment))
+    }
+
+    pub fn set_uniform(&self, name: &str, val: UniformData) {
+        self.uniforms.borrow_mut().insert(name.to_string(), val);
+    }
+
+    pub fn get_uniform(&self, name: &str) -> Option<UniformData> {
+        self.uniforms.borrow().get(name).copied()
+    }
+
+    pub fn bind_texture(&self, unit: u32, texture: Rc<SoftTexture>) {
+        self.textures.borrow_mut().insert(unit, texture);
+    }
+
+    /// Sample the texture bound to the unit a sampler uniform points at.
+    /// Sampling with nothing bound reads as opaque black, like an incomplete texture in GL.
+    fn sample(&self, sampler: &str, uv: Vector2<f32>) -> Vector4<f32> {
+        let unit = match self.get_uniform(sampler) {
+            Some(UniformData::Sampler(unit)) => unit,
+            Some(UniformData::Int(unit)) => unit as u32,
+            _ => 0,
+        };
+
+        match self.textures.borrow().get(&unit) {
+            Some(texture) => texture.sample(uv),
+            None => Vector4::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    // Unset uniforms read as zero, the same as an unset uniform in GL.
+
+    fn int(&self, name: &str) -> i32 {
+        match self.get_uniform(name) {
+            Some(UniformData::Int(x)) => x,
+            Some(UniformData::Bool(b)) => b as i32,
+            _ => 0,
+        }
+    }
+
+    fn vec2(&self, name: &str) -> Vector2<f32> {
+        match self.get_uniform(name) {
+            Some(UniformData::Vec2(v)) => v,
+            _ => Vector2::zeros(),
+        }
+    }
+
+    fn vec4(&self, name: &str) -> Vector4<f32> {
+        match self.get_uniform(name) {
+            Some(UniformData::Vec4(v)) => v,
+            _ => Vector4::zeros(),
+        }
+    }
+
+    fn mat4(&self, name: &str) -> Option<Matrix4<f32>> {
+        match self.get_uniform(name) {
+            Some(UniformData::Mat4(m)) => Some(m),
+            _ => None,
+        }
+    }
+
+    /// Shade a fragment, `depth` being its window space depth like `gl_FragCoord.z`.
+    fn shade(&self, frag: &Varyings, depth: f32) -> Vector4<f32> {
+        match self.shading {
+            SoftShading::VertexColor => frag.color,
+            SoftShading::FlatColor => {
+                let color = self.vec4("flatColor");
+                gamma(color.xyz()).push(color.w)
+            }
+            SoftShading::Source => self.sample("source", frag.texcoord),
+            SoftShading::PackedDepth => pack_depth(depth),
+            SoftShading::Lit => {
+                gamma(frag.color.xyz().component_mul(&self.irradiance(frag))).push(1.0)
+            }
+            SoftShading::Textured => {
+                let texel = self.sample("diffuseTexture", frag.texcoord);
+                let albedo = texel.xyz().map(|c| c.powf(2.2));
+                gamma(albedo.component_mul(&self.irradiance(frag))).push(texel.w)
+            }
+        }
+    }
+
+    /// The `irradiance` function shared by the built-in lit shaders.
+    fn irradiance(&self, frag: &Varyings) -> Vector3<f32> {
+        let ambient = self.vec4("ambientLightColor");
+        let mut total = ambient.xyz() * ambient.w;
+
+        let normal = match frag.normal.try_normalize(f32::EPSILON) {
+            Some(normal) => normal,
+            None => return total,
+        };
+
+        for i in 0..self.int("lightCount") {
+            let color = self.vec4(&format!("lightColor[{}]", i));
+            let position = self.vec4(&format!("lightPosition[{}]", i));
+            let spot = self.vec4(&format!("lightSpot[{}]", i));
+            let falloff = self.vec2(&format!("lightFalloff[{}]", i));
+
+            let to_light = position.xyz() - (frag.position * position.w);
+            let light_dir = to_light.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+
+            let mut attenuation = 1.0;
+            if position.w > 0.0 {
+                let dist = to_light.magnitude();
+                attenuation = 1.0 / (1.0 + dist * dist);
+
+                if falloff.x > 0.0 {
+                    let window = (1.0 - (dist / falloff.x).powi(4)).clamp(0.0, 1.0);
+                    attenuation *= window * window;
+                }
+            }
+
+            attenuation *= smoothstep(spot.w, falloff.y, (-light_dir).dot(&spot.xyz()));
+
+            let shade_factor = normal.dot(&light_dir).max(0.0);
+            total += color.xyz() * color.w * shade_factor * attenuation * self.shadow(i, frag.position, shade_factor);
+        }
+
+        total
+    }
+
+    /// The `shadow` function shared by the built-in lit shaders.
+    fn shadow(&self, light: i32, position: Vector3<f32>, shade_factor: f32) -> f32 {
+        if self.int("receiveShadows") == 0 {
+            return 1.0;
+        }
+
+        let mut visibility = 1.0;
+        for s in 0..MAX_SHADOWS {
+            if self.int(&format!("shadowLight[{}]", s)) != light {
+                continue;
+            }
+
+            let matrix = self.mat4(&format!("shadowMatrix[{}]", s)).unwrap_or_else(Matrix4::zeros);
+            let params = self.vec4(&format!("shadowParams[{}]", s));
+            let packed = self.int(&format!("shadowPacked[{}]", s)) != 0;
+            visibility *= self.shadow_factor(&format!("shadowMap[{}]", s), matrix, params, packed, position, shade_factor);
+        }
+
+        visibility
+    }
+
+    /// The `shadowFactor` function shared by the built-in lit shaders.
+    fn shadow_factor(&self, map: &str, matrix: Matrix4<f32>, params: Vector4<f32>, packed: bool, position: Vector3<f32>, shade_factor: f32) -> f32 {
+        let clip = matrix * position.push(1.0);
+        let coord = (clip.xyz() / clip.w) * 0.5 + Vector3::repeat(0.5);
+        if coord.x < 0.0 || coord.x > 1.0 || coord.y < 0.0 || coord.y > 1.0 || coord.z > 1.0 {
+            return 1.0;
+        }
+
+        let slope = (1.0 - shade_factor * shade_factor).max(0.0).sqrt() / shade_factor.max(0.1);
+        let bias = params.x + params.y * slope;
+
+        let radius = MAX_PCF_RADIUS as i32;
+        let mut lit = 0.0;
+        let mut samples = 0.0;
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                if x.abs() as f32 > params.w || y.abs() as f32 > params.w {
+                    continue;
+                }
+
+                let texel = self.sample(map, coord.xy() + Vector2::new(x as f32, y as f32) * params.z);
+                let depth = if packed { unpack_depth(texel) } else { texel.x };
+                lit += if coord.z - bias > depth { 0.0 } else { 1.0 };
+                samples += 1.0;
+            }
+        }
+
+        lit / samples
+    }
+}
+
+/// RGBA8 pixels kept in memory for the software renderer to sample from.
+/// Mipmaps are not generated, minification uses the same filter as magnification.
+#[derive(Debug)]
+pub struct SoftTexture {
+    pub width: u32,
+    pub height: u32,
+    // Written to while bound as a render target
+    data: RefCell<Vec<u8>>,
+    options: TextureOptions,
+}
+
+impl SoftTexture {
+    pub fn new(width: u32, height: u32, data: &[u8], options: &TextureOptions) -> Result<SoftTexture, ()> {
+        if data.len() != (width * height * 4) as usize {
+            return Err(());
+        }
+
+        Ok(SoftTexture { width, height, data: RefCell::new(data.to_vec()), options: *options })
+    }
+
+    pub fn sample(&self, uv: Vector2<f32>) -> Vector4<f32> {
+        if self.width == 0 || self.height == 0 {
+            return Vector4::new(0.0, 0.0, 0.0, 1.0);
+        }
+
+        // Texel centers sit on half coordinates
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+
+        match self.options.mag_filter {
+            TextureFilter::Nearest => self.texel(x.round() as i32, y.round() as i32),
+            TextureFilter::Linear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let top = self.texel(x0, y0).lerp(&self.texel(x0 + 1, y0), fx);
+                let bottom = self.texel(x0, y0 + 1).lerp(&self.texel(x0 + 1, y0 + 1), fx);
+                top.lerp(&bottom, fy)
+            }
+        }
+    }
+
+    fn texel(&self, x: i32, y: i32) -> Vector4<f32> {
+        let x = wrap(x, self.width as i32, self.options.wrap_s);
+        let y = wrap(y, self.height as i32, self.options.wrap_t);
+        let i = ((y * self.width as i32 + x) * 4) as usize;
+        let data = self.data.borrow();
+        Vector4::new(data[i], data[i + 1], data[i + 2], data[i + 3]).map(|c| c as f32 / 255.0)
+    }
+
+    /// Read a single texel, with `(0, 0)` being the first row of the data.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Vector4<u8> {
+        let i = ((y * self.width + x) * 4) as usize;
+        let data = self.data.borrow();
+        Vector4::new(data[i], data[i + 1], data[i + 2], data[i + 3])
+    }
+
+    fn write(&self, texel: usize, color: [u8; 4]) {
+        self.data.borrow_mut()[(texel * 4)..(texel * 4 + 4)].copy_from_slice(&color);
+    }
+
+    fn fill(&self, color: [u8; 4]) {
+        for pixel in self.data.borrow_mut().chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+/// The software side of a `RenderTarget`: a texture to draw into plus its depth buffer.
+/// Rows are stored bottom up, the same as a GL framebuffer, so the texture samples the right way up.
+#[derive(Debug)]
+pub struct SoftFramebuffer {
+    color: Rc<SoftTexture>,
+    depth: RefCell<Vec<f32>>,
+}
+
+impl SoftFramebuffer {
+    pub fn new(color: Rc<SoftTexture>) -> SoftFramebuffer {
+        let pixels = (color.width * color.height) as usize;
+        SoftFramebuffer { color, depth: RefCell::new(vec![1.0; pixels]) }
+    }
+
+    pub fn get_texture(&self) -> &Rc<SoftTexture> {
+        &self.color
+    }
+
+    fn size(&self) -> (i32, i32) {
+        (self.color.width as i32, self.color.height as i32)
+    }
+
+    /// Index of a pixel given with `(0, 0)` at the top left, like the renderer's own buffers.
+    fn index(&self, x: i32, y: i32) -> usize {
+        let (width, height) = self.size();
+        ((height - 1 - y) * width + x) as usize
+    }
+}
+
+fn wrap(i: i32, size: i32, mode: TextureWrap) -> i32 {
+    match mode {
+        TextureWrap::Repeat => i.rem_euclid(size),
+        TextureWrap::ClampToEdge => i.clamp(0, size - 1),
+        TextureWrap::MirroredRepeat => {
+            let i = i.rem_euclid(size * 2);
+            if i < size { i } else { (size * 2) - 1 - i }
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Spread depth over all four channels, as `packDepth` in `shadow.f.glsl`.
+fn pack_depth(depth: f32) -> Vector4<f32> {
+    let bits = (Vector4::new(1.0, 255.0, 65025.0, 16581375.0) * depth).map(|c| c.fract());
+    bits - Vector4::new(bits.y, bits.z, bits.w, bits.w).component_mul(&Vector4::new(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0))
+}
+
+fn unpack_depth(bits: Vector4<f32>) -> f32 {
+    bits.dot(&Vector4::new(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0))
+}
+
+fn gamma(color: Vector3<f32>) -> Vector3<f32> {
+    color.map(|c| c.max(0.0).powf(1.0 / 2.2))
+}
+
+/// Per-vertex values interpolated across a primitive.
+#[derive(Debug, Clone, Copy)]
+struct Varyings {
+    position: Vector3<f32>,
+    normal: Vector3<f32>,
+    color: Vector4<f32>,
+    texcoord: Vector2<f32>,
+}
+
+impl Varyings {
+    fn weighted(v: [&Varyings; 3], w: [f32; 3]) -> Varyings {
+        Varyings {
+            position: v[0].position * w[0] + v[1].position * w[1] + v[2].position * w[2],
+            normal: v[0].normal * w[0] + v[1].normal * w[1] + v[2].normal * w[2],
+            color: v[0].color * w[0] + v[1].color * w[1] + v[2].color * w[2],
+            texcoord: v[0].texcoord * w[0] + v[1].texcoord * w[1] + v[2].texcoord * w[2],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClipVertex {
+    position: Vector4<f32>,
+    varyings: Varyings,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            position: self.position.lerp(&other.position, t),
+            varyings: Varyings::weighted([&self.varyings, &other.varyings, &other.varyings], [1.0 - t, t, 0.0]),
+        }
+    }
+
+    /// Distance to the near clipping plane, positive when inside it.
+    fn near_distance(&self) -> f32 {
+        self.position.z + self.position.w
+    }
+}
+
+/// A vertex after the perspective divide, in pixel coordinates with y pointing down.
+#[derive(Debug, Clone, Copy)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    inv_w: f32,
+    varyings: Varyings,
+}
+
+/// A headless renderer that rasterizes into an in-memory RGBA8 + depth buffer.
+/// Mesh data is read straight from the CPU side of the mesh, so no buffers are ever created.
+pub struct SoftRenderer {
+    width: i32,
+    height: i32,
+    color: RefCell<Vec<u8>>,
+    depth: RefCell<Vec<f32>>,
+    depth_test: Cell<bool>,
+    program: RefCell<Option<Rc<SoftProgram>>>,
+    /// The `instanceModel` and `instanceColor` of the instance being drawn.
+    instance: Cell<Option<(Matrix4<f32>, Vector4<f32>)>>,
+    /// Drawn into instead of the color and depth buffers above when set.
+    target: RefCell<Option<Rc<SoftFramebuffer>>>,
+    preprocessor: RefCell<Preprocessor>,
+    globals: Cell<GlobalUniforms>,
+}
+
+impl Renderer for SoftRenderer {
+    fn create_shader(&self, vertex: &str, fragment: &str) -> Result<Shader, RenderError> {
+        self.create_shader_with_defines(vertex, fragment, &[])
+    }
+
+    /// The software pipeline reads the standard attributes straight from the mesh, so the layout is unused.
+    fn create_shader_with_layout(&self, vertex: &str, fragment: &str, _layout: &VertexLayout) -> Result<Shader, RenderError> {
+        self.create_shader(vertex, fragment)
+    }
+
+    /// Preprocessed like WebGL 1 sources, so the shading model is picked from the expanded source.
+    fn create_shader_with_defines(&self, vertex: &str, fragment: &str, defines: &[(&str, &str)]) -> Result<Shader, RenderError> {
+        let (vertex, fragment) = self.preprocessor.borrow().process_program(vertex, fragment, defines, GlBackend::WebGl1)?;
+        let (vertex, fragment) = (vertex.get_code(), fragment.get_code());
+        Ok(Shader::new_soft(SoftProgram::from_source(vertex, fragment), Reflection::from_source(vertex, fragment)).with_defines(defines))
+    }
+
+    fn get_preprocessor(&self) -> &RefCell<Preprocessor> {
+        &self.preprocessor
+    }
+
+    fn reload_shader(&self, shader: &Shader, vertex: &str, fragment: &str) -> Result<(), RenderError> {
+        let (vertex, fragment) = self.preprocessor.borrow().process_program(vertex, fragment, &shader.get_defines(), GlBackend::WebGl1)?;
+        shader.reload(&vertex, &fragment)
+    }
+
+    fn set_shader(&self, program: Option<&Shader>) {
+        if let Some(program) = program {
+            *self.program.borrow_mut() = program.soft_program();
+            self.globals.get().apply(program);
+        }
+    }
+
+    fn set_globals(&self, globals: GlobalUniforms) {
+        self.globals.set(globals);
+    }
+
+    fn get_globals(&self) -> GlobalUniforms {
+        self.globals.get()
+    }
+
+    fn draw_mesh(&self, mesh: &Mesh) {
+        self.draw_mesh_mode(mesh, mesh.draw_mode);
+    }
+
+    fn draw_mesh_mode(&self, mesh: &Mesh, draw_mode: u32) {
+        let program = match self.program.borrow().clone() {
+            Some(program) => program,
+            None => return,
+        };
+
+        // An index past the end of the mesh leaves a gap, dropping the primitives that use it
+        let transformed = self.transform_verticies(mesh, &program);
+        let verts: Vec<Option<ClipVertex>> = if mesh.using_indicies() {
+            mesh.get_indicies().iter().map(|i| transformed.get(*i as usize).copied()).collect()
+        } else {
+            transformed.into_iter().map(Some).collect()
+        };
+
+        let triangle = |a: &Option<ClipVertex>, b: &Option<ClipVertex>, c: &Option<ClipVertex>| {
+            if let (Some(a), Some(b), Some(c)) = (a, b, c) {
+                self.draw_triangle(&program, [a, b, c]);
+            }
+        };
+        let line = |a: &Option<ClipVertex>, b: &Option<ClipVertex>| {
+            if let (Some(a), Some(b)) = (a, b) {
+                self.draw_line(&program, a, b);
+            }
+        };
+
+        match draw_mode {
+            WebGlRenderingContext::TRIANGLES => {
+                for trig in verts.chunks_exact(3) {
+                    triangle(&trig[0], &trig[1], &trig[2]);
+                }
+            }
+            WebGlRenderingContext::TRIANGLE_STRIP => {
+                for i in 2..verts.len() {
+                    // Every other triangle is wound the opposite way to keep the strip consistent
+                    if i % 2 == 0 {
+                        triangle(&verts[i - 2], &verts[i - 1], &verts[i]);
+                    } else {
+                        triangle(&verts[i - 1], &verts[i - 2], &verts[i]);
+                    }
+                }
+            }
+            WebGlRenderingContext::TRIANGLE_FAN => {
+                for i in 2..verts.len() {
+                    triangle(&verts[0], &verts[i - 1], &verts[i]);
+                }
+            }
+            WebGlRenderingContext::LINES => {
+                for pair in verts.chunks_exact(2) {
+                    line(&pair[0], &pair[1]);
+                }
+            }
+            WebGlRenderingContext::LINE_STRIP | WebGlRenderingContext::LINE_LOOP => {
+                for pair in verts.windows(2) {
+                    line(&pair[0], &pair[1]);
+                }
+                if draw_mode == WebGlRenderingContext::LINE_LOOP && verts.len() > 2 {
+                    line(&verts[verts.len() - 1], &verts[0]);
+                }
+            }
+            WebGlRenderingContext::POINTS => {
+                for point in verts.iter().flatten() {
+                    self.draw_point(&program, point);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Drawn one instance at a time, reading `instanceModel` and `instanceColor` like `instanced.v.glsl`.
+    fn draw_mesh_instanced(&self, mesh: &Mesh, instances: &InstanceBuffer) {
+        for i in 0..instances.len() {
+            let model = instances.get_value(i, "instanceModel")
+                .filter(|v| v.len() == 16)
+                .map(Matrix4::from_column_slice)
+                .unwrap_or_else(Matrix4::identity);
+            let color = instances.get_value(i, "instanceColor")
+                .filter(|v| v.len() == 4)
+                .map(Vector4::from_column_slice)
+                .unwrap_or_else(|| Vector4::new(1.0, 1.0, 1.0, 1.0));
+
+            self.instance.set(Some((model, color)));
+            self.draw_mesh(mesh);
+        }
+        self.instance.set(None);
+    }
+
+    fn supports_instancing(&self) -> bool {
+        false
+    }
+
+    fn draw_scene(&self, scene: &Scene) {
+        scene.draw(self);
+    }
+
+    fn create_buffer(&self) -> Result<GpuBuffer, RenderError> {
+        Err(RenderError::Unsupported(String::from("the software renderer has no GL buffers")))
+    }
+
+    fn supports_uint_indicies(&self) -> bool {
+        true
+    }
+
+    fn create_texture(&self, width: u32, height: u32, data: &[u8], options: &TextureOptions) -> Result<Texture, RenderError> {
+        let texture = SoftTexture::new(width, height, data, options)
+            .map_err(|_| RenderError::Texture(format!("expected {} bytes of RGBA for {}x{}", width * height * 4, width, height)))?;
+        Ok(Texture::new_soft(texture))
+    }
+
+    fn create_texture_from_image(&self, _image: &HtmlImageElement, _options: &TextureOptions) -> Result<Texture, RenderError> {
+        Err(RenderError::Unsupported(String::from("the software renderer can't read images")))
+    }
+
+    fn create_texture_from_bitmap(&self, _bitmap: &ImageBitmap, _options: &TextureOptions) -> Result<Texture, RenderError> {
+        Err(RenderError::Unsupported(String::from("the software renderer can't read images")))
+    }
+
+    fn create_render_target(&self, width: u32, height: u32, options: &RenderTargetOptions) -> Result<RenderTarget, RenderError> {
+        RenderTarget::new_soft(width, height, options)
+            .map_err(|_| RenderError::RenderTarget(format!("unable to create a {}x{} target", width, height)))
+    }
+
+    fn set_render_target(&self, target: Option<&RenderTarget>) {
+        *self.target.borrow_mut() = target.and_then(|target| target.soft_framebuffer()).cloned();
+    }
+
+    fn read_pixels(&self, x: i32, y: i32, width: i32, height: i32) -> Result<Vec<u8>, RenderError> {
+        let (target_width, target_height) = self.target_size();
+        if x < 0 || y < 0 || width < 0 || height < 0 || x + width > target_width || y + height > target_height {
+            return Err(RenderError::RenderTarget(format!("reading {}x{} at ({}, {}) is outside the target", width, height, x, y)));
+        }
+
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in y..(y + height) {
+            for column in x..(x + width) {
+                pixels.extend(self.read_color(column, target_height - 1 - row));
+            }
+        }
+        Ok(pixels)
+    }
+
+    fn clear(&self, color: Vector4<f32>) {
+        let rgba = to_rgba8(color);
+        match self.target.borrow().as_ref() {
+            Some(target) => {
+                target.color.fill(rgba);
+                target.depth.borrow_mut().fill(1.0);
+            }
+            None => {
+                for pixel in self.color.borrow_mut().chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&rgba);
+                }
+                self.depth.borrow_mut().fill(1.0);
+            }
+        }
+    }
+
+    fn begin_render(&self) {
+        // The browser hands us a fresh depth buffer every frame, so do the same here
+        self.depth.borrow_mut().fill(1.0);
+    }
+
+    fn end_render(&self) {
+
+    }
+
+    fn enable_depth_test(&self) {
+        self.depth_test.set(true);
+    }
+
+    fn disable_depth_test(&self) {
+        self.depth_test.set(false);
+    }
+
+    fn get_width(&self) -> i32 {
+        self.width
+    }
+
+    fn get_height(&self) -> i32 {
+        self.height
+    }
+
+    fn aspect(&self) -> f32 {
+        (self.get_width() as f32) / (self.get_height() as f32)
+    }
+
+    fn get_gl(&self) -> Option<&WebGlRenderingContext> {
+        None
+    }
+}
+
+impl SoftRenderer {
+    pub fn new(width: i32, height: i32) -> SoftRenderer {
+        let pixels = (width.max(0) * height.max(0)) as usize;
+        SoftRenderer {
+            width,
+            height,
+            color: RefCell::new(vec![0; pixels * 4]),
+            depth: RefCell::new(vec![1.0; pixels]),
+            depth_test: Cell::new(false),
+            program: RefCell::new(None),
+            instance: Cell::new(None),
+            target: RefCell::new(None),
+            preprocessor: RefCell::new(Preprocessor::new()),
+            globals: Cell::new(GlobalUniforms::new()),
+        }
+    }
+
+    /// Resize the framebuffer, discarding its contents.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        let pixels = (width.max(0) * height.max(0)) as usize;
+        self.width = width;
+        self.height = height;
+        self.color = RefCell::new(vec![0; pixels * 4]);
+        self.depth = RefCell::new(vec![1.0; pixels]);
+    }
+
+    /// The color buffer as tightly packed RGBA8, starting from the top left corner.
+    pub fn pixels(&self) -> Ref<'_, Vec<u8>> {
+        self.color.borrow()
+    }
+
+    /// Read a single pixel, with `(0, 0)` being the top left corner.
+    pub fn get_pixel(&self, x: i32, y: i32) -> Vector4<u8> {
+        let i = self.index(x, y) * 4;
+        let color = self.color.borrow();
+        Vector4::new(color[i], color[i + 1], color[i + 2], color[i + 3])
+    }
+
+    /// Read a single depth value in the range `[0, 1]`, with `(0, 0)` being the top left corner.
+    pub fn get_depth(&self, x: i32, y: i32) -> f32 {
+        self.depth.borrow()[self.index(x, y)]
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        assert!(x >= 0 && x < self.width && y >= 0 && y < self.height, "pixel ({}, {}) out of bounds", x, y);
+        (y * self.width + x) as usize
+    }
+
+    /// Size of whatever is being drawn into, the bound render target or the framebuffer.
+    fn target_size(&self) -> (i32, i32) {
+        match self.target.borrow().as_ref() {
+            Some(target) => target.size(),
+            None => (self.width, self.height),
+        }
+    }
+
+    /// Read a pixel of whatever is being drawn into, with `(0, 0)` being the top left corner.
+    fn read_color(&self, x: i32, y: i32) -> [u8; 4] {
+        match self.target.borrow().as_ref() {
+            Some(target) => {
+                let i = target.index(x, y) * 4;
+                let data = target.color.data.borrow();
+                [data[i], data[i + 1], data[i + 2], data[i + 3]]
+            }
+            None => self.get_pixel(x, y).into(),
+        }
+    }
+
+    /// Run the vertex stage, mirroring `simple.v.glsl`, or `instanced.v.glsl` during an instanced draw.
+    /// Attributes that are not in use read as `(0, 0, 0, 1)` just like a disabled attribute array.
+    fn transform_verticies(&self, mesh: &Mesh, program: &SoftProgram) -> Vec<ClipVertex> {
+        let (instance_model, instance_color) = self.instance.get()
+            .unwrap_or_else(|| (Matrix4::identity(), Vector4::new(1.0, 1.0, 1.0, 1.0)));
+        let mvp = program.mat4("mvp").unwrap_or_else(Matrix4::identity) * instance_model;
+        let model = program.mat4("model").unwrap_or_else(Matrix4::identity) * instance_model;
+        let normal_matrix = (program.mat4("normalMatrix").unwrap_or_else(Matrix4::identity) * instance_model)
+            .fixed_view::<3, 3>(0, 0).into_owned();
+
+        mesh.get_verticies().iter().enumerate().map(|(i, vert)| {
+            let normal = match mesh.get_normals().get(i) {
+                Some(n) if mesh.using_normals() => normal_matrix * n,
+                _ => Vector3::zeros(),
+            };
+            let color = match mesh.get_colors().get(i) {
+                Some(c) if mesh.using_colors() => c.component_mul(&instance_color),
+                _ => Vector4::new(0.0, 0.0, 0.0, 1.0).component_mul(&instance_color),
+            };
+            let texcoord = match mesh.get_texcoords().get(i) {
+                Some(t) if mesh.using_texcoords() => *t,
+                _ => Vector2::zeros(),
+            };
+
+            let position = vert.push(1.0);
+            ClipVertex {
+                position: mvp * position,
+                varyings: Varyings { position: (model * position).xyz(), normal, color, texcoord },
+            }
+        }).collect()
+    }
+
+    fn to_screen(&self, vert: &ClipVertex) -> ScreenVertex {
+        let (width, height) = self.target_size();
+        let inv_w = 1.0 / vert.position.w;
+        let ndc = vert.position.xyz() * inv_w;
+        ScreenVertex {
+            x: (ndc.x * 0.5 + 0.5) * width as f32,
+            y: (0.5 - ndc.y * 0.5) * height as f32,
+            z: ndc.z * 0.5 + 0.5,
+            inv_w,
+            varyings: vert.varyings,
+        }
+    }
+
+    fn draw_triangle(&self, program: &SoftProgram, trig: [&ClipVertex; 3]) {
+        // Clip against the near plane so nothing behind the camera gets projected
+        let mut polygon: Vec<ClipVertex> = Vec::with_capacity(4);
+        for i in 0..3 {
+            let a = trig[i];
+            let b = trig[(i + 1) % 3];
+            let (da, db) = (a.near_distance(), b.near_distance());
+
+            if da >= 0.0 {
+                polygon.push(*a);
+            }
+            if (da >= 0.0) != (db >= 0.0) {
+                polygon.push(a.lerp(b, da / (da - db)));
+            }
+        }
+
+        if polygon.len() < 3 {
+            return;
+        }
+
+        let screen: Vec<ScreenVertex> = polygon.iter().map(|v| self.to_screen(v)).collect();
+        for i in 1..(screen.len() - 1) {
+            self.rasterize_triangle(program, [&screen[0], &screen[i], &screen[i + 1]]);
+        }
+    }
+
+    fn rasterize_triangle(&self, program: &SoftProgram, v: [&ScreenVertex; 3]) {
+        let edge = |a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32| (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x);
+
+        let area = edge(v[0], v[1], v[2].x, v[2].y);
+        if area == 0.0 {
+            return;
+        }
+
+        let (width, height) = self.target_size();
+        let min_x = v.iter().map(|p| p.x).fold(f32::INFINITY, f32::min).floor().max(0.0) as i32;
+        let max_x = v.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max).ceil().min(width as f32) as i32;
+        let min_y = v.iter().map(|p| p.y).fold(f32::INFINITY, f32::min).floor().max(0.0) as i32;
+        let max_y = v.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max).ceil().min(height as f32) as i32;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let b = [
+                    edge(v[1], v[2], px, py) / area,
+                    edge(v[2], v[0], px, py) / area,
+                    edge(v[0], v[1], px, py) / area,
+                ];
+
+                if b.iter().any(|w| *w < 0.0) {
+                    continue;
+                }
+
+                let z = b[0] * v[0].z + b[1] * v[1].z + b[2] * v[2].z;
+                self.shade_fragment(program, x, y, z, [v[0], v[1], v[2]], b);
+            }
+        }
+    }
+
+    fn draw_line(&self, program: &SoftProgram, a: &ClipVertex, b: &ClipVertex) {
+        let (da, db) = (a.near_distance(), b.near_distance());
+        if da < 0.0 && db < 0.0 {
+            return;
+        }
+
+        let a = if da < 0.0 { b.lerp(a, db / (db - da)) } else { *a };
+        let b = if db < 0.0 { a.lerp(b, da / (da - db)) } else { *b };
+        let (a, b) = (self.to_screen(&a), self.to_screen(&b));
+
+        let (width, height) = self.target_size();
+        let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil().max(1.0) as i32;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let (x, y) = ((a.x + (b.x - a.x) * t).floor() as i32, (a.y + (b.y - a.y) * t).floor() as i32);
+            if x < 0 || x >= width || y < 0 || y >= height {
+                continue;
+            }
+
+            let z = a.z + (b.z - a.z) * t;
+            self.shade_fragment(program, x, y, z, [&a, &b, &b], [1.0 - t, t, 0.0]);
+        }
+    }
+
+    fn draw_point(&self, program: &SoftProgram, point: &ClipVertex) {
+        if point.near_distance() < 0.0 {
+            return;
+        }
+
+        let (width, height) = self.target_size();
+        let p = self.to_screen(point);
+        let (x, y) = (p.x.floor() as i32, p.y.floor() as i32);
+        if x < 0 || x >= width || y < 0 || y >= height {
+            return;
+        }
+
+        self.shade_fragment(program, x, y, p.z, [&p, &p, &p], [1.0, 0.0, 0.0]);
+    }
+
+    /// Depth test, shade and write a single fragment.
+    /// `screen_weights` are linear in screen space and get corrected for perspective here.
+    fn shade_fragment(&self, program: &SoftProgram, x: i32, y: i32, z: f32, v: [&ScreenVertex; 3], screen_weights: [f32; 3]) {
+        if !(0.0..=1.0).contains(&z) {
+            return;
+        }
+
+        let target = self.target.borrow();
+        let i = match target.as_ref() {
+            Some(target) => target.index(x, y),
+            None => self.index(x, y),
+        };
+
+        if self.depth_test.get() {
+            let mut depth = match target.as_ref() {
+                Some(target) => target.depth.borrow_mut(),
+                None => self.depth.borrow_mut(),
+            };
+            if z >= depth[i] {
+                return;
+            }
+            depth[i] = z;
+        }
+
+        let mut w = [
+            screen_weights[0] * v[0].inv_w,
+            screen_weights[1] * v[1].inv_w,
+            screen_weights[2] * v[2].inv_w,
+        ];
+        let sum = w[0] + w[1] + w[2];
+        w.iter_mut().for_each(|w| *w /= sum);
+
+        let frag = Varyings::weighted([&v[0].varyings, &v[1].varyings, &v[2].varyings], w);
+        let color = to_rgba8(program.shade(&frag, z));
+        match target.as_ref() {
+            Some(target) => target.color.write(i, color),
+            None => self.color.borrow_mut()[(i * 4)..(i * 4 + 4)].copy_from_slice(&color),
+        }
+    }
+}
+
+fn to_rgba8(color: Vector4<f32>) -> [u8; 4] {
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+    use crate::shader::SHADER_SIMPLE_VERT;
+
+    /// Declares no uniforms, so it shades with the interpolated vertex color.
+    const VERTEX_COLOR_FRAG: &str = "precision mediump float;\nvarying vec4 vColor;\nvoid main() {\n    gl_FragColor = vColor;\n}\n";
+
+    /// A triangle covering the bottom left half of the screen at depth `z` in clip space.
+    fn triangle(z: f32, color: Vector4<f32>) -> Mesh {
+        let mut mesh = Mesh::new();
+        mesh.add_verticies(vec![vector!(-1.0, -1.0, z), vector!(1.0, -1.0, z), vector!(-1.0, 1.0, z)]);
+        mesh.add_colors(vec![color; 3]);
+        mesh.use_colors = true;
+        mesh
+    }
+
+    fn renderer() -> (SoftRenderer, Shader) {
+        let render = SoftRenderer::new(8, 8);
+        let shader = render.create_shader(SHADER_SIMPLE_VERT, VERTEX_COLOR_FRAG).unwrap();
+        render.clear(vector!(0.0, 0.0, 0.0, 1.0));
+        render.set_shader(Some(&shader));
+        (render, shader)
+    }
+
+    #[test]
+    fn draws_triangle() {
+        let (render, _shader) = renderer();
+        // Like GL, depth is only written with the test on
+        render.enable_depth_test();
+        render.draw_mesh(&triangle(0.0, vector!(1.0, 0.0, 0.0, 1.0)));
+
+        assert_eq!(render.get_pixel(1, 6), vector!(255, 0, 0, 255));
+        assert_eq!(render.get_pixel(6, 1), vector!(0, 0, 0, 255));
+        assert!((render.get_depth(1, 6) - 0.5).abs() < 1e-4);
+        assert_eq!(render.get_depth(6, 1), 1.0);
+    }
+
+    #[test]
+    fn depth_test_keeps_nearest() {
+        let (render, _shader) = renderer();
+        render.enable_depth_test();
+        render.draw_mesh(&triangle(0.0, vector!(1.0, 0.0, 0.0, 1.0)));
+        render.draw_mesh(&triangle(0.5, vector!(0.0, 1.0, 0.0, 1.0)));
+        assert_eq!(render.get_pixel(1, 6), vector!(255, 0, 0, 255));
+
+        render.draw_mesh(&triangle(-0.5, vector!(0.0, 0.0, 1.0, 1.0)));
+        assert_eq!(render.get_pixel(1, 6), vector!(0, 0, 255, 255));
+        assert!((render.get_depth(1, 6) - 0.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn skips_triangles_with_bad_indicies() {
+        let (render, _shader) = renderer();
+        let mut mesh = triangle(0.0, vector!(1.0, 0.0, 0.0, 1.0));
+        mesh.add_vertex(vector!(1.0, 1.0, 0.0));
+        mesh.add_color(vector!(1.0, 0.0, 0.0, 1.0));
+        mesh.add_indicies(vec![1, 3, 7, 1, 3, 2]);
+        mesh.use_indicies = true;
+        render.draw_mesh(&mesh);
+
+        // Only the second triangle, covering the top right half, is drawn
+        assert_eq!(render.get_pixel(6, 1), vector!(255, 0, 0, 255));
+        assert_eq!(render.get_pixel(1, 6), vector!(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn read_pixels_starts_at_bottom_left() {
+        let (render, _shader) = renderer();
+        render.draw_mesh(&triangle(0.0, vector!(1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(render.read_pixels(1, 1, 1, 1).unwrap(), vec![255, 0, 0, 255]);
+        assert_eq!(render.read_pixels(6, 6, 1, 1).unwrap(), vec![0, 0, 0, 255]);
+        assert!(render.read_pixels(7, 7, 2, 2).is_err());
+    }
+}
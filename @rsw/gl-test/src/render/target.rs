@@ -0,0 +1,265 @@
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext, WebGl2RenderingContext, WebGlFramebuffer, WebGlRenderbuffer};
+use crate::render::Renderer;
+use crate::render::soft::{SoftFramebuffer, SoftTexture};
+use crate::render::texture::{Texture, TextureFilter, TextureOptions, TextureWrap};
+use crate::render::resource::{ResourceKind, created, deleted};
+
+/// From OES_texture_half_float, which web-sys only exposes on the extension object.
+const HALF_FLOAT_OES: u32 = 0x8D61;
+
+/// The color storage of a render target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFormat {
+    Rgba8,
+    /// Half float color for HDR rendering.
+    Rgba16F,
+    Rgba32F,
+}
+
+impl ColorFormat {
+    /// The `(internal format, format, type)` to allocate a texture of this format with.
+    pub fn gl_formats(&self, webgl2: bool) -> (i32, u32, u32) {
+        match (self, webgl2) {
+            (ColorFormat::Rgba8, true) => (WebGl2RenderingContext::RGBA8 as i32, WebGl2RenderingContext::RGBA, WebGl2RenderingContext::UNSIGNED_BYTE),
+            (ColorFormat::Rgba16F, true) => (WebGl2RenderingContext::RGBA16F as i32, WebGl2RenderingContext::RGBA, WebGl2RenderingContext::HALF_FLOAT),
+            (ColorFormat::Rgba32F, true) => (WebGl2RenderingContext::RGBA32F as i32, WebGl2RenderingContext::RGBA, WebGl2RenderingContext::FLOAT),
+            // WebGL 1 only has unsized formats, the type alone picks the precision
+            (ColorFormat::Rgba8, false) => (WebGlRenderingContext::RGBA as i32, WebGlRenderingContext::RGBA, WebGlRenderingContext::UNSIGNED_BYTE),
+            (ColorFormat::Rgba16F, false) => (WebGlRenderingContext::RGBA as i32, WebGlRenderingContext::RGBA, HALF_FLOAT_OES),
+            (ColorFormat::Rgba32F, false) => (WebGlRenderingContext::RGBA as i32, WebGlRenderingContext::RGBA, WebGlRenderingContext::FLOAT),
+        }
+    }
+
+    /// The extensions needed to render into this format.
+    pub fn required_extensions(&self, webgl2: bool) -> &'static [&'static str] {
+        match (self, webgl2) {
+            (ColorFormat::Rgba8, _) => &[],
+            (_, true) => &["EXT_color_buffer_float"],
+            (ColorFormat::Rgba16F, false) => &["OES_texture_half_float", "EXT_color_buffer_half_float"],
+            (ColorFormat::Rgba32F, false) => &["OES_texture_float", "WEBGL_color_buffer_float"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderTargetOptions {
+    pub format: ColorFormat,
+    /// Attach a depth buffer so depth testing works while drawing into the target.
+    pub depth: bool,
+    /// Attach depth as a texture that can be sampled afterwards, see `get_depth_texture`.
+    /// Needs WebGL 2 or WEBGL_depth_texture, and is ignored by the software renderer.
+    pub depth_texture: bool,
+    /// How the color texture is filtered when sampled.
+    pub filter: TextureFilter,
+    /// Follow the canvas size multiplied by this factor, see `RenderTarget::update_size`.
+    /// `None` keeps the size the target was created with.
+    pub canvas_scale: Option<f32>,
+}
+
+impl Default for RenderTargetOptions {
+    fn default() -> Self {
+        RenderTargetOptions {
+            format: ColorFormat::Rgba8,
+            depth: true,
+            depth_texture: false,
+            filter: TextureFilter::Linear,
+            canvas_scale: None,
+        }
+    }
+}
+
+enum TargetBackend {
+    Gl {
+        framebuffer: WebGlFramebuffer,
+        depth: Option<WebGlRenderbuffer>,
+        gl: WebGlRenderingContext,
+    },
+    Soft(Rc<SoftFramebuffer>),
+}
+
+/// An offscreen framebuffer to draw into instead of the canvas, see `Renderer::set_render_target`.
+/// Its color texture can be sampled like any other texture once drawing into it is done.
+pub struct RenderTarget {
+    backend: TargetBackend,
+    color: Rc<Texture>,
+    depth_texture: Option<Rc<Texture>>,
+    options: RenderTargetOptions,
+}
+
+impl RenderTarget {
+    pub fn new_gl(gl: &WebGlRenderingContext, width: u32, height: u32, options: &RenderTargetOptions) -> Result<RenderTarget, JsValue> {
+        let color = Texture::new_render_texture(gl, width, height, options.format, options.filter)?;
+        // Textures are created up front, so failing to create one doesn't leave the framebuffer behind
+        let depth_texture = match options.depth && options.depth_texture {
+            true => Some(Rc::new(Texture::new_depth_texture(gl, width, height)?)),
+            false => None,
+        };
+        let framebuffer = gl.create_framebuffer().ok_or_else(|| JsValue::from_str("Unable to create framebuffer object"))?;
+        created(ResourceKind::Framebuffer);
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            WebGlRenderingContext::FRAMEBUFFER,
+            WebGlRenderingContext::COLOR_ATTACHMENT0,
+            WebGlRenderingContext::TEXTURE_2D,
+            color.texture(),
+            0,
+        );
+
+        let depth = match options.depth {
+            true if options.depth_texture => {
+                gl.framebuffer_texture_2d(
+                    WebGlRenderingContext::FRAMEBUFFER,
+                    WebGlRenderingContext::DEPTH_ATTACHMENT,
+                    WebGlRenderingContext::TEXTURE_2D,
+                    depth_texture.as_ref().and_then(|t| t.texture()),
+                    0,
+                );
+                None
+            }
+            true => {
+                let renderbuffer = gl.create_renderbuffer().ok_or_else(|| JsValue::from_str("Unable to create renderbuffer object"))?;
+                created(ResourceKind::Renderbuffer);
+                gl.bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, Some(&renderbuffer));
+                gl.renderbuffer_storage(WebGlRenderingContext::RENDERBUFFER, depth_format(gl), width as i32, height as i32);
+                gl.framebuffer_renderbuffer(
+                    WebGlRenderingContext::FRAMEBUFFER,
+                    WebGlRenderingContext::DEPTH_ATTACHMENT,
+                    WebGlRenderingContext::RENDERBUFFER,
+                    Some(&renderbuffer),
+                );
+                Some(renderbuffer)
+            }
+            false => None,
+        };
+
+        let status = gl.check_framebuffer_status(WebGlRenderingContext::FRAMEBUFFER);
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, None);
+
+        let target = RenderTarget {
+            backend: TargetBackend::Gl { framebuffer, depth, gl: gl.clone() },
+            color: Rc::new(color),
+            depth_texture,
+            options: *options,
+        };
+
+        if status != WebGlRenderingContext::FRAMEBUFFER_COMPLETE {
+            return Err(JsValue::from_str(&format!("Framebuffer is incomplete (0x{:x})", status)));
+        }
+
+        Ok(target)
+    }
+
+    /// A render target for the software renderer. Every format is stored as RGBA8.
+    pub fn new_soft(width: u32, height: u32, options: &RenderTargetOptions) -> Result<RenderTarget, ()> {
+        let texture = SoftTexture::new(width, height, &vec![0; (width * height * 4) as usize], &TextureOptions {
+            wrap_s: TextureWrap::ClampToEdge,
+            wrap_t: TextureWrap::ClampToEdge,
+            min_filter: options.filter,
+            mag_filter: options.filter,
+            mipmaps: false,
+        })?;
+
+        let color = Texture::new_soft(texture);
+        let framebuffer = SoftFramebuffer::new(color.soft_texture().ok_or(())?.clone());
+        Ok(RenderTarget {
+            backend: TargetBackend::Soft(Rc::new(framebuffer)),
+            color: Rc::new(color),
+            depth_texture: None,
+            options: *options,
+        })
+    }
+
+    /// Reallocate the target at a new size, discarding its contents.
+    /// On the software renderer this replaces the color texture, so get it again afterwards.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+        if let TargetBackend::Gl { depth, gl, .. } = &self.backend {
+            self.color.resize_storage(width, height, self.options.format)?;
+            if let Some(depth) = depth {
+                gl.bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, Some(depth));
+                gl.renderbuffer_storage(WebGlRenderingContext::RENDERBUFFER, depth_format(gl), width as i32, height as i32);
+            }
+            if let Some(depth_texture) = &self.depth_texture {
+                depth_texture.resize_depth_storage(width, height)?;
+            }
+            return Ok(());
+        }
+
+        *self = RenderTarget::new_soft(width, height, &self.options)
+            .map_err(|_| JsValue::from_str("Unable to create software render target"))?;
+        Ok(())
+    }
+
+    /// Resize to follow the canvas if the target was created with a `canvas_scale`.
+    /// Call once a frame before drawing into the target, returns whether it was resized.
+    pub fn update_size(&mut self, render: &dyn Renderer) -> Result<bool, JsValue> {
+        let scale = match self.options.canvas_scale {
+            Some(scale) => scale,
+            None => return Ok(false),
+        };
+
+        let width = ((render.get_width() as f32 * scale).round() as u32).max(1);
+        let height = ((render.get_height() as f32 * scale).round() as u32).max(1);
+        if width == self.get_width() && height == self.get_height() {
+            return Ok(false);
+        }
+
+        self.resize(width, height)?;
+        Ok(true)
+    }
+
+    pub fn get_width(&self) -> u32 { self.color.get_width() }
+    pub fn get_height(&self) -> u32 { self.color.get_height() }
+    pub fn get_options(&self) -> &RenderTargetOptions { &self.options }
+
+    pub fn aspect(&self) -> f32 {
+        (self.get_width() as f32) / (self.get_height() as f32)
+    }
+
+    /// The color attachment, for sampling what was drawn.
+    pub fn get_texture(&self) -> &Rc<Texture> {
+        &self.color
+    }
+
+    /// The depth attachment, if the target was created with `depth_texture`.
+    pub fn get_depth_texture(&self) -> Option<&Rc<Texture>> {
+        self.depth_texture.as_ref()
+    }
+
+    /// The WebGL framebuffer object, or `None` if this target belongs to the software renderer.
+    pub fn framebuffer(&self) -> Option<&WebGlFramebuffer> {
+        match &self.backend {
+            TargetBackend::Gl { framebuffer, .. } => Some(framebuffer),
+            TargetBackend::Soft(_) => None,
+        }
+    }
+
+    pub fn soft_framebuffer(&self) -> Option<&Rc<SoftFramebuffer>> {
+        match &self.backend {
+            TargetBackend::Gl { .. } => None,
+            TargetBackend::Soft(framebuffer) => Some(framebuffer),
+        }
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        if let TargetBackend::Gl { framebuffer, depth, gl } = &self.backend {
+            gl.delete_framebuffer(Some(framebuffer));
+            deleted(ResourceKind::Framebuffer);
+            if let Some(depth) = depth {
+                gl.delete_renderbuffer(Some(depth));
+                deleted(ResourceKind::Renderbuffer);
+            }
+        }
+    }
+}
+
+/// 24 bit depth where available, WebGL 1 only guarantees 16.
+fn depth_format(gl: &WebGlRenderingContext) -> u32 {
+    match gl.dyn_ref::<WebGl2RenderingContext>() {
+        Some(_) => WebGl2RenderingContext::DEPTH_COMPONENT24,
+        None => WebGlRenderingContext::DEPTH_COMPONENT16,
+    }
+}
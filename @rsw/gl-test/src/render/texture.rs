@@ -0,0 +1,307 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext, WebGl2RenderingContext, WebGlTexture, HtmlImageElement, ImageBitmap};
+use crate::render::soft::SoftTexture;
+use crate::render::target::ColorFormat;
+use crate::render::resource::{ResourceKind, created, deleted};
+use crate::log;
+
+macro_rules! console_log { ($($t:tt)*) => (log(&format!("[texture] {}", &format_args!($($t)*)).to_string())) }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureWrap {
+    Repeat,
+    ClampToEdge,
+    MirroredRepeat,
+}
+
+impl TextureWrap {
+    pub fn gl_enum(&self) -> u32 {
+        match self {
+            TextureWrap::Repeat => WebGlRenderingContext::REPEAT,
+            TextureWrap::ClampToEdge => WebGlRenderingContext::CLAMP_TO_EDGE,
+            TextureWrap::MirroredRepeat => WebGlRenderingContext::MIRRORED_REPEAT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureOptions {
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    pub mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            wrap_s: TextureWrap::Repeat,
+            wrap_t: TextureWrap::Repeat,
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            mipmaps: true,
+        }
+    }
+}
+
+enum TextureBackend {
+    Gl {
+        texture: WebGlTexture,
+        gl: WebGlRenderingContext,
+    },
+    Soft(Rc<SoftTexture>),
+}
+
+pub struct Texture {
+    backend: TextureBackend,
+    // Render targets reallocate their texture in place when resized
+    width: Cell<u32>,
+    height: Cell<u32>,
+}
+
+impl Texture {
+    /// Create a texture from tightly packed RGBA8 pixels. As usual for GL the first row is the bottom
+    /// of the texture, where v is 0, so images stored top down come out upside down.
+    pub fn from_rgba(
+        gl: &WebGlRenderingContext,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        options: &TextureOptions,
+    ) -> Result<Texture, JsValue> {
+        // GL would only flag a short buffer with an error code, leaving the texture empty
+        if data.len() != (width * height * 4) as usize {
+            return Err(JsValue::from_str(&format!("expected {} bytes of RGBA for {}x{}", width * height * 4, width, height)));
+        }
+
+        let texture = Texture::create(gl, width, height)?;
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGlRenderingContext::TEXTURE_2D,
+            0,
+            WebGlRenderingContext::RGBA as i32,
+            width as i32,
+            height as i32,
+            0,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(data),
+        )?;
+        texture.apply_options(gl, options);
+        Ok(texture)
+    }
+
+    pub fn from_image(
+        gl: &WebGlRenderingContext,
+        image: &HtmlImageElement,
+        options: &TextureOptions,
+    ) -> Result<Texture, JsValue> {
+        let texture = Texture::create(gl, image.natural_width(), image.natural_height())?;
+        gl.tex_image_2d_with_u32_and_u32_and_image(
+            WebGlRenderingContext::TEXTURE_2D,
+            0,
+            WebGlRenderingContext::RGBA as i32,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            image,
+        )?;
+        texture.apply_options(gl, options);
+        Ok(texture)
+    }
+
+    pub fn from_bitmap(
+        gl: &WebGlRenderingContext,
+        bitmap: &ImageBitmap,
+        options: &TextureOptions,
+    ) -> Result<Texture, JsValue> {
+        let texture = Texture::create(gl, bitmap.width(), bitmap.height())?;
+        gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            WebGlRenderingContext::TEXTURE_2D,
+            0,
+            WebGlRenderingContext::RGBA as i32,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            bitmap,
+        )?;
+        texture.apply_options(gl, options);
+        Ok(texture)
+    }
+
+    /// Create a texture with uninitialised contents for rendering into,
+    /// clamped at the edges and without mipmaps so any size works on WebGL 1.
+    pub fn new_render_texture(
+        gl: &WebGlRenderingContext,
+        width: u32,
+        height: u32,
+        format: ColorFormat,
+        filter: TextureFilter,
+    ) -> Result<Texture, JsValue> {
+        let texture = Texture::create(gl, width, height)?;
+        texture.resize_storage(width, height, format)?;
+        texture.apply_options(gl, &TextureOptions {
+            wrap_s: TextureWrap::ClampToEdge,
+            wrap_t: TextureWrap::ClampToEdge,
+            min_filter: filter,
+            mag_filter: filter,
+            mipmaps: false,
+        });
+        Ok(texture)
+    }
+
+    /// A depth texture for rendering into, to be sampled as a single channel holding depth in `[0, 1]`.
+    /// Depth textures only support nearest filtering. Needs WebGL 2 or the WEBGL_depth_texture extension.
+    pub fn new_depth_texture(gl: &WebGlRenderingContext, width: u32, height: u32) -> Result<Texture, JsValue> {
+        let texture = Texture::create(gl, width, height)?;
+        texture.resize_depth_storage(width, height)?;
+        texture.apply_options(gl, &TextureOptions {
+            wrap_s: TextureWrap::ClampToEdge,
+            wrap_t: TextureWrap::ClampToEdge,
+            min_filter: TextureFilter::Nearest,
+            mag_filter: TextureFilter::Nearest,
+            mipmaps: false,
+        });
+        Ok(texture)
+    }
+
+    /// Reallocate the texture's storage at a new size, discarding its contents.
+    pub(crate) fn resize_storage(&self, width: u32, height: u32, format: ColorFormat) -> Result<(), JsValue> {
+        let webgl2 = self.gl().is_some_and(|gl| gl.dyn_ref::<WebGl2RenderingContext>().is_some());
+        self.allocate(width, height, format.gl_formats(webgl2))
+    }
+
+    pub(crate) fn resize_depth_storage(&self, width: u32, height: u32) -> Result<(), JsValue> {
+        let formats = match self.gl().and_then(|gl| gl.dyn_ref::<WebGl2RenderingContext>()) {
+            Some(_) => (WebGl2RenderingContext::DEPTH_COMPONENT24 as i32, WebGl2RenderingContext::DEPTH_COMPONENT, WebGl2RenderingContext::UNSIGNED_INT),
+            None => (WebGlRenderingContext::DEPTH_COMPONENT as i32, WebGlRenderingContext::DEPTH_COMPONENT, WebGlRenderingContext::UNSIGNED_INT),
+        };
+        self.allocate(width, height, formats)
+    }
+
+    fn gl(&self) -> Option<&WebGlRenderingContext> {
+        match &self.backend {
+            TextureBackend::Gl { gl, .. } => Some(gl),
+            TextureBackend::Soft(_) => None,
+        }
+    }
+
+    /// Allocate uninitialised storage given as `(internal format, format, type)`.
+    fn allocate(&self, width: u32, height: u32, (internal_format, format, ty): (i32, u32, u32)) -> Result<(), JsValue> {
+        let (texture, gl) = match &self.backend {
+            TextureBackend::Gl { texture, gl } => (texture, gl),
+            TextureBackend::Soft(_) => return Err(JsValue::from_str("Software textures can't be resized in place")),
+        };
+
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGlRenderingContext::TEXTURE_2D,
+            0,
+            internal_format,
+            width as i32,
+            height as i32,
+            0,
+            format,
+            ty,
+            None,
+        )?;
+
+        self.width.set(width);
+        self.height.set(height);
+        Ok(())
+    }
+
+    /// Wrap a texture for the software renderer.
+    pub fn new_soft(texture: SoftTexture) -> Texture {
+        let (width, height) = (texture.width, texture.height);
+        Texture { backend: TextureBackend::Soft(Rc::new(texture)), width: Cell::new(width), height: Cell::new(height) }
+    }
+
+    /// Create an empty texture object and leave it bound to `TEXTURE_2D` for uploading.
+    fn create(gl: &WebGlRenderingContext, width: u32, height: u32) -> Result<Texture, JsValue> {
+        let texture = gl.create_texture().ok_or_else(|| JsValue::from_str("Unable to create texture object"))?;
+        created(ResourceKind::Texture);
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+        Ok(Texture { backend: TextureBackend::Gl { texture, gl: gl.clone() }, width: Cell::new(width), height: Cell::new(height) })
+    }
+
+    /// WebGL 1 can only repeat and mipmap textures with power of two dimensions,
+    /// anything else falls back to clamping without mipmaps. WebGL 2 has no such limit.
+    fn apply_options(&self, gl: &WebGlRenderingContext, options: &TextureOptions) {
+        let mut options = *options;
+        let webgl2 = gl.dyn_ref::<WebGl2RenderingContext>().is_some();
+        if !webgl2 && !self.is_power_of_two() {
+            if options.mipmaps || options.wrap_s != TextureWrap::ClampToEdge || options.wrap_t != TextureWrap::ClampToEdge {
+                console_log!("{}x{} texture is not a power of two, clamping without mipmaps.", self.get_width(), self.get_height());
+            }
+            options.wrap_s = TextureWrap::ClampToEdge;
+            options.wrap_t = TextureWrap::ClampToEdge;
+            options.mipmaps = false;
+        }
+
+        let min_filter = match (options.min_filter, options.mipmaps) {
+            (TextureFilter::Nearest, false) => WebGlRenderingContext::NEAREST,
+            (TextureFilter::Linear, false) => WebGlRenderingContext::LINEAR,
+            (TextureFilter::Nearest, true) => WebGlRenderingContext::NEAREST_MIPMAP_NEAREST,
+            (TextureFilter::Linear, true) => WebGlRenderingContext::LINEAR_MIPMAP_LINEAR,
+        };
+        let mag_filter = match options.mag_filter {
+            TextureFilter::Nearest => WebGlRenderingContext::NEAREST,
+            TextureFilter::Linear => WebGlRenderingContext::LINEAR,
+        };
+
+        gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_WRAP_S, options.wrap_s.gl_enum() as i32);
+        gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_WRAP_T, options.wrap_t.gl_enum() as i32);
+        gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_MIN_FILTER, min_filter as i32);
+        gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+        if options.mipmaps {
+            gl.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
+        }
+    }
+
+    fn is_power_of_two(&self) -> bool {
+        self.get_width().is_power_of_two() && self.get_height().is_power_of_two()
+    }
+
+    pub fn get_width(&self) -> u32 { self.width.get() }
+    pub fn get_height(&self) -> u32 { self.height.get() }
+
+    /// The WebGL texture object, or `None` if this texture belongs to the software renderer.
+    pub fn texture(&self) -> Option<&WebGlTexture> {
+        match &self.backend {
+            TextureBackend::Gl { texture, .. } => Some(texture),
+            TextureBackend::Soft(_) => None,
+        }
+    }
+
+    pub fn soft_texture(&self) -> Option<&Rc<SoftTexture>> {
+        match &self.backend {
+            TextureBackend::Gl { .. } => None,
+            TextureBackend::Soft(texture) => Some(texture),
+        }
+    }
+
+    /// Bind this texture to a texture unit, counting up from zero.
+    pub fn bind(&self, unit: u32) {
+        if let TextureBackend::Gl { texture, gl } = &self.backend {
+            gl.active_texture(WebGlRenderingContext::TEXTURE0 + unit);
+            gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(texture));
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        if let TextureBackend::Gl { texture, gl } = &self.backend {
+            gl.delete_texture(Some(texture));
+            deleted(ResourceKind::Texture);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use nalgebra::{ Vector3, Matrix4, UnitQuaternion, Point3 };
+use crate::render::Renderer;
+use crate::render::error::RenderError;
+use crate::render::camera::Camera;
+use crate::render::mesh::Mesh;
+use crate::render::light::{AmbientLight, LightSet, LightSource, MAX_LIGHTS};
+use crate::render::material::{DrawQueue, Material};
+use crate::render::shadow::ShadowPass;
+
+pub type NodeId = usize;
+
+/// A mesh and the material it is drawn with. Materials are shared so that meshes
+/// using the same one are drawn together.
+pub struct MeshComponent {
+    pub mesh: Mesh,
+    pub material: Rc<Material>,
+    /// Drawn into the shadow maps of shadow casting lights.
+    pub casts_shadows: bool,
+    /// Darkened where a shadow casting light is blocked, when drawn with shadows.
+    pub receives_shadows: bool,
+}
+
+impl MeshComponent {
+    pub fn new(mesh: Mesh, material: Rc<Material>) -> MeshComponent {
+        MeshComponent { mesh, material, casts_shadows: true, receives_shadows: true }
+    }
+}
+
+pub enum Component {
+    Mesh(MeshComponent),
+    /// A light in the node's local space, moved along with the node.
+    Light(LightSource),
+    /// Views the scene from the node it is attached to.
+    Camera(Camera),
+}
+
+pub struct Node {
+    pub name: String,
+    translation: Vector3<f32>,
+    rotation: UnitQuaternion<f32>,
+    scale: Vector3<f32>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Cell<Matrix4<f32>>,
+    dirty: Cell<bool>,
+    pub components: Vec<Component>,
+}
+
+impl Node {
+    fn new(name: &str, parent: Option<NodeId>) -> Node {
+        Node {
+            name: name.to_string(),
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            parent,
+            children: Vec::new(),
+            world: Cell::new(Matrix4::identity()),
+            dirty: Cell::new(true),
+            components: Vec::new(),
+        }
+    }
+
+    pub fn get_translation(&self) -> Vector3<f32> { self.translation }
+    pub fn get_rotation(&self) -> UnitQuaternion<f32> { self.rotation }
+    pub fn get_scale(&self) -> Vector3<f32> { self.scale }
+    pub fn get_parent(&self) -> Option<NodeId> { self.parent }
+    pub fn get_children(&self) -> &Vec<NodeId> { &self.children }
+
+    /// Transform from this node's space into its parent's.
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    pub fn add_component(&mut self, component: Component) {
+        self.components.push(component);
+    }
+
+    pub fn meshes(&self) -> impl Iterator<Item = &MeshComponent> {
+        self.components.iter().filter_map(|c| match c {
+            Component::Mesh(mesh) => Some(mesh),
+            _ => None,
+        })
+    }
+
+    pub fn meshes_mut(&mut self) -> impl Iterator<Item = &mut MeshComponent> {
+        self.components.iter_mut().filter_map(|c| match c {
+            Component::Mesh(mesh) => Some(mesh),
+            _ => None,
+        })
+    }
+
+    pub fn camera(&self) -> Option<&Camera> {
+        self.components.iter().find_map(|c| match c {
+            Component::Camera(camera) => Some(camera),
+            _ => None,
+        })
+    }
+}
+
+/// Why a change to the node hierarchy was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneError {
+    /// No node has this id, or it has been removed.
+    UnknownNode(NodeId),
+    /// Parenting `node` under `parent` would make it its own ancestor.
+    Cycle { node: NodeId, parent: NodeId },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::UnknownNode(id) => write!(f, "No node with id {}", id),
+            SceneError::Cycle { node, parent } => write!(f, "Parenting node {} under {} would make it its own ancestor", node, parent),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// A hierarchy of nodes, each positioned relative to its parent.
+/// World matrices are cached and only recomputed for nodes whose transform, or whose
+/// ancestors' transform, changed since they were last read.
+pub struct Scene {
+    nodes: Vec<Option<Node>>,
+    pub ambient: AmbientLight,
+    /// The node whose camera component the scene is drawn through.
+    pub camera: Option<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            nodes: Vec::new(),
+            ambient: AmbientLight { color: Vector3::new(1.0, 1.0, 1.0), intensity: 0.0 },
+            camera: None,
+        }
+    }
+
+    /// Add an empty node, either at the root or as a child of `parent`.
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Some(Node::new(name, parent)));
+
+        if let Some(parent) = parent.and_then(|p| self.get_node_mut(p)) {
+            parent.children.push(id);
+        }
+
+        id
+    }
+
+    /// Remove a node along with all of its children.
+    pub fn remove_node(&mut self, id: NodeId) {
+        let node = match self.nodes.get_mut(id).and_then(|n| n.take()) {
+            Some(node) => node,
+            None => return,
+        };
+
+        if let Some(parent) = node.parent.and_then(|p| self.get_node_mut(p)) {
+            parent.children.retain(|c| *c != id);
+        }
+
+        for child in node.children {
+            self.remove_node(child);
+        }
+
+        if self.camera == Some(id) {
+            self.camera = None;
+        }
+    }
+
+    /// Move a node under a new parent, failing if either node doesn't exist or that would make it its own ancestor.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        let old_parent = self.get_node(id).ok_or(SceneError::UnknownNode(id))?.parent;
+
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            if a == id {
+                return Err(SceneError::Cycle { node: id, parent: parent.unwrap() });
+            }
+            ancestor = self.get_node(a).ok_or(SceneError::UnknownNode(a))?.parent;
+        }
+
+        if let Some(old) = old_parent.and_then(|p| self.get_node_mut(p)) {
+            old.children.retain(|c| *c != id);
+        }
+        if let Some(new) = parent.and_then(|p| self.get_node_mut(p)) {
+            new.children.push(id);
+        }
+
+        self.get_node_mut(id).ok_or(SceneError::UnknownNode(id))?.parent = parent;
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    pub fn get_node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id).and_then(|n| n.as_ref())
+    }
+
+    pub fn get_node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id).and_then(|n| n.as_mut())
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().filter_map(|(id, n)| n.as_ref().map(|n| (id, n)))
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes().filter(|(_, n)| n.parent.is_none()).map(|(id, _)| id)
+    }
+
+    pub fn set_translation(&mut self, id: NodeId, translation: Vector3<f32>) {
+        if let Some(node) = self.get_node_mut(id) {
+            node.translation = translation;
+            self.mark_dirty(id);
+        }
+    }
+
+    pub fn set_rotation(&mut self, id: NodeId, rotation: UnitQuaternion<f32>) {
+        if let Some(node) = self.get_node_mut(id) {
+            node.rotation = rotation;
+            self.mark_dirty(id);
+        }
+    }
+
+    pub fn set_scale(&mut self, id: NodeId, scale: Vector3<f32>) {
+        if let Some(node) = self.get_node_mut(id) {
+            node.scale = scale;
+            self.mark_dirty(id);
+        }
+    }
+
+    fn mark_dirty(&self, id: NodeId) {
+        if let Some(node) = self.get_node(id) {
+            node.dirty.set(true);
+            for child in &node.children {
+                self.mark_dirty(*child);
+            }
+        }
+    }
+
+    /// Transform from a node's space into world space.
+    pub fn world_matrix(&self, id: NodeId) -> Matrix4<f32> {
+        let node = match self.get_node(id) {
+            Some(node) => node,
+            None => return Matrix4::identity(),
+        };
+
+        if node.dirty.get() {
+            let parent = node.parent.map(|p| self.world_matrix(p)).unwrap_or_else(Matrix4::identity);
+            node.world.set(parent * node.local_matrix());
+            node.dirty.set(false);
+        }
+
+        node.world.get()
+    }
+
+    /// Upload the buffers of every mesh in the scene.
+    pub fn update_buffers(&mut self, render: &dyn Renderer) -> Result<(), RenderError> {
+        for node in self.nodes.iter_mut().flatten() {
+            for mesh in node.meshes_mut() {
+                mesh.mesh.update_buffers(render)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Gather the lights attached to nodes, moved into world space.
+    /// Lights past the maximum the shaders support are dropped.
+    pub fn collect_lights(&self) -> LightSet {
+        let mut lights = LightSet::new(MAX_LIGHTS);
+        lights.ambient = self.ambient;
+
+        for (id, node) in self.nodes() {
+            let world = self.world_matrix(id);
+            for component in &node.components {
+                let light = match component {
+                    Component::Light(LightSource::Directional(light)) => {
+                        let mut light = *light;
+                        light.direction = world.transform_vector(&light.direction);
+                        LightSource::Directional(light)
+                    }
+                    Component::Light(LightSource::Point(light)) => {
+                        let mut light = *light;
+                        light.position = world.transform_point(&Point3::from(light.position)).coords;
+                        LightSource::Point(light)
+                    }
+                    Component::Light(LightSource::Spot(light)) => {
+                        let mut light = *light;
+                        light.position = world.transform_point(&Point3::from(light.position)).coords;
+                        light.direction = world.transform_vector(&light.direction);
+                        LightSource::Spot(light)
+                    }
+                    _ => continue,
+                };
+
+                if lights.add(light).is_err() {
+                    return lights;
+                }
+            }
+        }
+
+        lights
+    }
+
+    /// Every mesh that casts shadows, with its model matrix.
+    pub fn shadow_casters(&self) -> Vec<(&Mesh, Matrix4<f32>)> {
+        let mut casters = Vec::new();
+        for (id, node) in self.nodes() {
+            let model = self.world_matrix(id);
+            for component in node.meshes().filter(|m| m.casts_shadows) {
+                casters.push((&component.mesh, model));
+            }
+        }
+        casters
+    }
+
+    /// Update and draw the shadow maps for the scene's lights, ahead of `draw_with_shadows`.
+    /// Leaves the canvas bound.
+    pub fn draw_shadows(&self, render: &dyn Renderer, shadows: &mut ShadowPass) -> Result<(), RenderError> {
+        shadows.update(render, &self.collect_lights())?;
+        shadows.draw(render, &self.shadow_casters());
+        Ok(())
+    }
+
+    /// Draw every mesh in the scene through the active camera, grouped by material.
+    /// Uniforms a shader does not declare are skipped, so unlit shaders can be mixed in freely.
+    pub fn draw(&self, render: &dyn Renderer) {
+        self.draw_queue(render, None);
+    }
+
+    /// Draw like `draw`, with the shadow maps from `draw_shadows` falling on meshes that receive them.
+    pub fn draw_with_shadows(&self, render: &dyn Renderer, shadows: &ShadowPass) {
+        self.draw_queue(render, Some(shadows));
+    }
+
+    fn draw_queue(&self, render: &dyn Renderer, shadows: Option<&ShadowPass>) {
+        let (view, projection) = match self.camera.and_then(|id| self.get_node(id).map(|n| (id, n))) {
+            Some((id, node)) => (
+                self.world_matrix(id).try_inverse().unwrap_or_else(Matrix4::identity),
+                node.camera().map(|c| c.projection_for(render)).unwrap_or_else(Matrix4::identity),
+            ),
+            None => (Matrix4::identity(), Matrix4::identity()),
+        };
+
+        let lights = self.collect_lights();
+
+        let mut queue = DrawQueue::new();
+        if let Some(shadows) = shadows {
+            queue.set_shadows(shadows);
+        }
+        for (id, node) in self.nodes() {
+            let model = self.world_matrix(id);
+            for component in node.meshes() {
+                queue.push_with_shadows(&component.mesh, &component.material, model, component.receives_shadows);
+            }
+        }
+
+        queue.submit(render, projection * view, Some(&lights));
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_parent_reports_why_it_failed() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None);
+        let child = scene.add_node("child", Some(root));
+
+        assert_eq!(scene.set_parent(root, Some(child)), Err(SceneError::Cycle { node: root, parent: child }));
+        assert_eq!(scene.set_parent(root, Some(root)), Err(SceneError::Cycle { node: root, parent: root }));
+        assert_eq!(scene.set_parent(child, Some(7)), Err(SceneError::UnknownNode(7)));
+        assert_eq!(scene.set_parent(7, None), Err(SceneError::UnknownNode(7)));
+        assert_eq!(scene.get_node(child).unwrap().get_parent(), Some(root));
+
+        scene.set_parent(child, None).unwrap();
+        assert!(scene.get_node(root).unwrap().get_children().is_empty());
+    }
+}
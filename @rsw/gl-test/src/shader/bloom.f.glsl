@@ -0,0 +1,34 @@
+precision mediump float;
+
+varying vec2 frag_texcoord;
+
+uniform sampler2D source;
+uniform vec2 resolution;
+// Brightness above which colors start to glow
+uniform float bloomThreshold;
+uniform float bloomIntensity;
+// Size of the glow in pixels
+uniform float bloomRadius;
+
+vec3 bright(vec2 uv) {
+    return max(texture2D(source, uv).rgb - vec3(bloomThreshold), 0.0);
+}
+
+void main() {
+    vec4 color = texture2D(source, frag_texcoord);
+    vec2 spacing = bloomRadius / (3.0 * resolution);
+
+    // A single gaussian weighted pass, cheaper to set up than separate blur passes
+    vec3 glow = vec3(0.0);
+    float total = 0.0;
+    for (int x = -3; x <= 3; x++) {
+        for (int y = -3; y <= 3; y++) {
+            vec2 offset = vec2(float(x), float(y));
+            float weight = exp(-dot(offset, offset) / 4.5);
+            glow += bright(frag_texcoord + offset * spacing) * weight;
+            total += weight;
+        }
+    }
+
+    gl_FragColor = vec4(color.rgb + (glow / total) * bloomIntensity, color.a);
+}
@@ -0,0 +1,50 @@
+// Lights from `LightSet::apply`, summed up by `irradiance`.
+
+#include "shadows"
+
+#ifndef MAX_LIGHTS
+#define MAX_LIGHTS 8
+#endif
+
+uniform vec4 ambientLightColor;
+
+// Color in rgb and intensity in a
+uniform vec4 lightColor[MAX_LIGHTS];
+// Position of the light, or the direction towards it when w is 0
+uniform vec4 lightPosition[MAX_LIGHTS];
+// Direction the spot points in, and the cosine of its outer cone
+uniform vec4 lightSpot[MAX_LIGHTS];
+// Range the light fades out over, and the cosine of the inner spot cone
+uniform vec2 lightFalloff[MAX_LIGHTS];
+uniform int lightCount;
+
+vec3 irradiance(vec3 position, vec3 normal) {
+    vec3 total = ambientLightColor.rgb * ambientLightColor.a;
+
+    for (int i = 0; i < MAX_LIGHTS; i++) {
+        if (i >= lightCount) {
+            break;
+        }
+
+        vec3 toLight = lightPosition[i].xyz - (position * lightPosition[i].w);
+        vec3 lightDir = normalize(toLight);
+
+        float attenuation = 1.0;
+        if (lightPosition[i].w > 0.0) {
+            float dist = length(toLight);
+            attenuation = 1.0 / (1.0 + dist * dist);
+
+            if (lightFalloff[i].x > 0.0) {
+                float window = clamp(1.0 - pow(dist / lightFalloff[i].x, 4.0), 0.0, 1.0);
+                attenuation *= window * window;
+            }
+        }
+
+        attenuation *= smoothstep(lightSpot[i].w, lightFalloff[i].y, dot(-lightDir, lightSpot[i].xyz));
+
+        float shadeFactor = max(0.0, dot(normal, lightDir));
+        total += (lightColor[i].rgb * lightColor[i].a) * shadeFactor * attenuation * shadow(i, position, shadeFactor);
+    }
+
+    return total;
+}
@@ -0,0 +1,70 @@
+// Shadow maps from `ShadowPass::apply`, looked up by `shadow`.
+
+#ifndef MAX_SHADOWS
+#define MAX_SHADOWS 2
+#endif
+#ifndef MAX_PCF_RADIUS
+#define MAX_PCF_RADIUS 2
+#endif
+
+// Depth from the point of view of each shadow casting light
+uniform sampler2D shadowMap[MAX_SHADOWS];
+// Transform from world space into the light's clip space
+uniform mat4 shadowMatrix[MAX_SHADOWS];
+// Index of the light casting each shadow, -1 when the slot is unused
+uniform int shadowLight[MAX_SHADOWS];
+// Constant bias, slope scaled bias, size of a texel, and PCF radius in texels
+uniform vec4 shadowParams[MAX_SHADOWS];
+// Whether depth is packed into RGBA rather than read from a depth texture
+uniform int shadowPacked[MAX_SHADOWS];
+// Zero for meshes that don't receive shadows
+uniform int receiveShadows;
+
+float unpackDepth(vec4 bits) {
+    return dot(bits, vec4(1.0, 1.0 / 255.0, 1.0 / 65025.0, 1.0 / 16581375.0));
+}
+
+// Fraction of the light reaching a point, averaged over the texels around it
+float shadowFactor(sampler2D map, mat4 matrix, vec4 params, bool isPacked, vec3 position, float shadeFactor) {
+    vec4 clip = matrix * vec4(position, 1.0);
+    vec3 coord = (clip.xyz / clip.w) * 0.5 + 0.5;
+    if (coord.x < 0.0 || coord.x > 1.0 || coord.y < 0.0 || coord.y > 1.0 || coord.z > 1.0) {
+        return 1.0;
+    }
+
+    // Surfaces at grazing angles need more bias to keep from shadowing themselves
+    float slope = sqrt(1.0 - shadeFactor * shadeFactor) / max(shadeFactor, 0.1);
+    float bias = params.x + params.y * slope;
+
+    float lit = 0.0;
+    float samples = 0.0;
+    for (int x = -MAX_PCF_RADIUS; x <= MAX_PCF_RADIUS; x++) {
+        for (int y = -MAX_PCF_RADIUS; y <= MAX_PCF_RADIUS; y++) {
+            if (abs(float(x)) > params.w || abs(float(y)) > params.w) {
+                continue;
+            }
+
+            vec4 texel = texture2D(map, coord.xy + vec2(float(x), float(y)) * params.z);
+            float depth = isPacked ? unpackDepth(texel) : texel.r;
+            lit += (coord.z - bias > depth) ? 0.0 : 1.0;
+            samples += 1.0;
+        }
+    }
+
+    return lit / samples;
+}
+
+float shadow(int light, vec3 position, float shadeFactor) {
+    float visibility = 1.0;
+    if (receiveShadows == 0) {
+        return visibility;
+    }
+
+    for (int s = 0; s < MAX_SHADOWS; s++) {
+        if (shadowLight[s] == light) {
+            visibility *= shadowFactor(shadowMap[s], shadowMatrix[s], shadowParams[s], shadowPacked[s] != 0, position, shadeFactor);
+        }
+    }
+
+    return visibility;
+}
@@ -0,0 +1,9 @@
+precision mediump float;
+
+varying vec2 frag_texcoord;
+
+uniform sampler2D source;
+
+void main() {
+    gl_FragColor = texture2D(source, frag_texcoord);
+}
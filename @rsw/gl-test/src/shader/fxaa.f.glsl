@@ -0,0 +1,48 @@
+precision mediump float;
+
+// Fast approximate anti-aliasing: blur along edges found from the luma of the
+// neighbouring pixels, after the simplified FXAA by Timothy Lottes.
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+#define FXAA_SPAN_MAX 8.0
+
+varying vec2 frag_texcoord;
+
+uniform sampler2D source;
+uniform vec2 resolution;
+
+void main() {
+    vec2 texel = 1.0 / resolution;
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    vec4 center = texture2D(source, frag_texcoord);
+    float lumaM = dot(center.rgb, luma);
+    float lumaNW = dot(texture2D(source, frag_texcoord + vec2(-1.0, 1.0) * texel).rgb, luma);
+    float lumaNE = dot(texture2D(source, frag_texcoord + vec2(1.0, 1.0) * texel).rgb, luma);
+    float lumaSW = dot(texture2D(source, frag_texcoord + vec2(-1.0, -1.0) * texel).rgb, luma);
+    float lumaSE = dot(texture2D(source, frag_texcoord + vec2(1.0, -1.0) * texel).rgb, luma);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Blur along the edge, perpendicular to the luma gradient
+    vec2 dir = vec2((lumaNW + lumaNE) - (lumaSW + lumaSE), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 rgbA = 0.5 * (
+        texture2D(source, frag_texcoord + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture2D(source, frag_texcoord + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        texture2D(source, frag_texcoord + dir * -0.5).rgb +
+        texture2D(source, frag_texcoord + dir * 0.5).rgb);
+
+    // The wider blur picked up something from across the edge, use the narrow one
+    float lumaB = dot(rgbB, luma);
+    if (lumaB < lumaMin || lumaB > lumaMax) {
+        gl_FragColor = vec4(rgbA, center.a);
+    } else {
+        gl_FragColor = vec4(rgbB, center.a);
+    }
+}
@@ -0,0 +1,25 @@
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+
+varying vec2 frag_texcoord;
+
+uniform sampler2D source;
+uniform vec2 resolution;
+uniform float time;
+// Largest change in brightness the grain makes
+uniform float grainAmount;
+
+float random(vec2 p) {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main() {
+    vec4 color = texture2D(source, frag_texcoord);
+    // Snap to whole pixels so the grain stays sharp, and move it every frame
+    vec2 pixel = floor(frag_texcoord * resolution) + fract(time) * 100.0;
+    float noise = random(pixel) - 0.5;
+    gl_FragColor = vec4(color.rgb + noise * grainAmount, color.a);
+}
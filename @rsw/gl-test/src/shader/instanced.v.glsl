@@ -0,0 +1,29 @@
+precision mediump float;
+
+attribute vec4 position;
+attribute vec3 normal;
+attribute vec4 color;
+attribute vec2 texcoord;
+
+// Per instance, see InstanceBuffer::standard
+attribute mat4 instanceModel;
+attribute vec4 instanceColor;
+
+varying vec3 frag_position;
+varying vec3 frag_normal;
+varying vec4 frag_color;
+varying vec2 frag_texcoord;
+
+uniform mat4 mvp;
+uniform mat4 model;
+uniform mat4 normalMatrix;
+uniform float time;
+
+void main() {
+    vec4 local = instanceModel * position;
+    gl_Position = mvp * local;
+    frag_position = (model * local).xyz;
+    frag_normal = normalize(mat3(normalMatrix) * mat3(instanceModel) * normal);
+    frag_color = color * instanceColor;
+    frag_texcoord = texcoord;
+}
\ No newline at end of file
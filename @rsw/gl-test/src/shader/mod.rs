@@ -1,107 +1,626 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use web_sys::*;
 use nalgebra::{ Vector1, Vector2, Vector3, Vector4, Matrix2, Matrix3, Matrix4 };
+use crate::render::soft::SoftProgram;
+use crate::render::texture::Texture;
+use crate::render::layout::VertexLayout;
+use crate::render::error::{RenderError, ShaderStage};
+use crate::render::gl2::GlBackend;
+use crate::render::resource::{ResourceKind, created, deleted};
+use crate::log;
+use preprocess::{Preprocessor, ShaderSource};
+use reflect::{Reflection, ShaderVariable, split_index, uniform_data_type};
+
+pub mod preprocess;
+pub mod reflect;
+
+macro_rules! console_log { ($($t:tt)*) => (log(&format!("[shader] {}", &format_args!($($t)*)).to_string())) }
 
 pub const SHADER_SIMPLE_VERT: &str = include_str!("./simple.v.glsl");
 pub const SHADER_SIMPLE_FRAG: &str = include_str!("./simple.f.glsl");
 pub const SHADER_FLATCOLOR_FRAG: &str = include_str!("./flatcolor.f.glsl");
+pub const SHADER_SIMPLE_TEXTURED_FRAG: &str = include_str!("./simple_textured.f.glsl");
+pub const SHADER_INSTANCED_VERT: &str = include_str!("./instanced.v.glsl");
+pub const SHADER_POST_VERT: &str = include_str!("./post.v.glsl");
+pub const SHADER_COPY_FRAG: &str = include_str!("./copy.f.glsl");
+pub const SHADER_BLOOM_FRAG: &str = include_str!("./bloom.f.glsl");
+pub const SHADER_VIGNETTE_FRAG: &str = include_str!("./vignette.f.glsl");
+pub const SHADER_GRAIN_FRAG: &str = include_str!("./grain.f.glsl");
+pub const SHADER_FXAA_FRAG: &str = include_str!("./fxaa.f.glsl");
+pub const SHADER_SHADOW_VERT: &str = include_str!("./shadow.v.glsl");
+pub const SHADER_SHADOW_FRAG: &str = include_str!("./shadow.f.glsl");
+
+/// A single uniform value as handed to one of the `set_uniform*` setters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformData {
+    Int(i32),
+    Bool(bool),
+    /// The texture unit a sampler reads from.
+    Sampler(u32),
+    IVec2(Vector2<i32>),
+    IVec3(Vector3<i32>),
+    IVec4(Vector4<i32>),
+    Float(f32),
+    Vec2(Vector2<f32>),
+    Vec3(Vector3<f32>),
+    Vec4(Vector4<f32>),
+    Mat2(Matrix2<f32>),
+    Mat3(Matrix3<f32>),
+    Mat4(Matrix4<f32>),
+}
+
+impl UniformData {
+    /// The components of a float based value in the order GL takes them, matrices being column major.
+    fn as_floats(&self) -> Option<&[f32]> {
+        match self {
+            UniformData::Float(x) => Some(std::slice::from_ref(x)),
+            UniformData::Vec2(v) => Some(v.as_slice()),
+            UniformData::Vec3(v) => Some(v.as_slice()),
+            UniformData::Vec4(v) => Some(v.as_slice()),
+            UniformData::Mat2(m) => Some(m.as_slice()),
+            UniformData::Mat3(m) => Some(m.as_slice()),
+            UniformData::Mat4(m) => Some(m.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// The components of an int, bool or sampler value, bools uploading as 0 or 1.
+    fn as_ints(&self) -> Option<Vec<i32>> {
+        match *self {
+            UniformData::Int(x) => Some(vec![x]),
+            UniformData::Bool(b) => Some(vec![b as i32]),
+            UniformData::Sampler(unit) => Some(vec![unit as i32]),
+            UniformData::IVec2(v) => Some(v.as_slice().to_vec()),
+            UniformData::IVec3(v) => Some(v.as_slice().to_vec()),
+            UniformData::IVec4(v) => Some(v.as_slice().to_vec()),
+            _ => None,
+        }
+    }
+}
+
+/// A Rust value that can be uploaded as a uniform, so `Shader::set_uniform` and `Material::set`
+/// take it directly. Implemented for the scalar, vector and matrix types GLSL has uniforms of.
+pub trait UniformValue {
+    fn to_uniform_data(&self) -> UniformData;
+}
+
+macro_rules! uniform_value {
+    ($($t:ty => $variant:ident),* $(,)?) => {
+        $(impl UniformValue for $t {
+            fn to_uniform_data(&self) -> UniformData {
+                UniformData::$variant(*self)
+            }
+        })*
+    }
+}
+
+uniform_value!(
+    i32 => Int,
+    bool => Bool,
+    Vector2<i32> => IVec2,
+    Vector3<i32> => IVec3,
+    Vector4<i32> => IVec4,
+    f32 => Float,
+    Vector2<f32> => Vec2,
+    Vector3<f32> => Vec3,
+    Vector4<f32> => Vec4,
+    Matrix2<f32> => Mat2,
+    Matrix3<f32> => Mat3,
+    Matrix4<f32> => Mat4,
+);
+
+impl UniformValue for Vector1<f32> {
+    fn to_uniform_data(&self) -> UniformData {
+        UniformData::Float(self[0])
+    }
+}
+
+impl UniformValue for UniformData {
+    fn to_uniform_data(&self) -> UniformData {
+        *self
+    }
+}
+
+enum ShaderBackend {
+    Gl {
+        program: RefCell<WebGlProgram>,
+        gl: WebGlRenderingContext,
+    },
+    Soft(RefCell<Rc<SoftProgram>>),
+}
 
 pub struct Shader {
-    pub program: WebGlProgram,
-    gl: WebGlRenderingContext,
+    backend: ShaderBackend,
     uniforms: RefCell<HashMap<String, WebGlUniformLocation>>,
+    /// The last value set on each uniform, so setting the same value again skips the GL call,
+    /// or the write to the software program.
+    /// Array elements are stored by their indexed name, `lightColor[0]` rather than `lightColor`.
+    values: RefCell<HashMap<String, UniformData>>,
+    attributes: RefCell<Rc<HashMap<String, u32>>>,
+    reflection: RefCell<Reflection>,
+    /// Uniform names already warned about, so each is only reported once.
+    warned: RefCell<HashSet<String>>,
+    /// What the program was created with, to build it the same way on `reload`.
+    layout: VertexLayout,
+    defines: Vec<(String, String)>,
 }
 
 impl Shader {
-    /// Create a new Shader program from a vertex and fragment shader
+    /// Create a new Shader program from a vertex and fragment shader, using the standard vertex layout.
     pub fn new(
         gl: &WebGlRenderingContext,
         vert_shader: &str,
         frag_shader: &str,
-    ) -> Result<Shader, JsValue> {
-        let vert_shader = compile_shader(&gl, WebGlRenderingContext::VERTEX_SHADER, vert_shader)?;
-        let frag_shader = compile_shader(&gl, WebGlRenderingContext::FRAGMENT_SHADER, frag_shader)?;
-        let program = link_program(&gl, &vert_shader, &frag_shader)?;
+    ) -> Result<Shader, RenderError> {
+        Shader::new_with_layout(gl, vert_shader, frag_shader, &VertexLayout::standard())
+    }
+
+    /// Create a new Shader program with the attributes of `layout` bound to its locations.
+    /// Includes resolve against the built-in chunks only, see `Renderer::get_preprocessor` for more.
+    pub fn new_with_layout(
+        gl: &WebGlRenderingContext,
+        vert_shader: &str,
+        frag_shader: &str,
+        layout: &VertexLayout,
+    ) -> Result<Shader, RenderError> {
+        let backend = if gl.dyn_ref::<WebGl2RenderingContext>().is_some() { GlBackend::WebGl2 } else { GlBackend::WebGl1 };
+        let (vert_shader, frag_shader) = Preprocessor::new().process_program(vert_shader, frag_shader, &[], backend)?;
+        Shader::from_sources(gl, &vert_shader, &frag_shader, layout)
+    }
+
+    /// Create a new Shader program from already preprocessed sources.
+    pub fn from_sources(
+        gl: &WebGlRenderingContext,
+        vert_shader: &ShaderSource,
+        frag_shader: &ShaderSource,
+        layout: &VertexLayout,
+    ) -> Result<Shader, RenderError> {
+        let program = build_program(gl, vert_shader, frag_shader, layout)?;
 
         let uniforms = RefCell::new(HashMap::new());
+        let attributes = RefCell::new(Rc::new(active_attributes(gl, &program)));
+        let reflection = RefCell::new(Reflection::from_program(gl, &program));
 
-        Ok(Shader { program, gl: gl.clone(), uniforms })
+        Ok(Shader {
+            backend: ShaderBackend::Gl { program: RefCell::new(program), gl: gl.clone() },
+            uniforms,
+            values: RefCell::new(HashMap::new()),
+            attributes,
+            reflection,
+            warned: RefCell::new(HashSet::new()),
+            layout: layout.clone(),
+            defines: Vec::new(),
+        })
+    }
+
+    /// Wrap a program for the software renderer, along with what its source declares.
+    /// Uniforms set on this shader are stored on the program rather than uploaded anywhere.
+    pub fn new_soft(program: SoftProgram, reflection: Reflection) -> Shader {
+        Shader {
+            backend: ShaderBackend::Soft(RefCell::new(Rc::new(program))),
+            uniforms: RefCell::new(HashMap::new()),
+            values: RefCell::new(HashMap::new()),
+            attributes: RefCell::new(Rc::new(HashMap::new())),
+            reflection: RefCell::new(reflection),
+            warned: RefCell::new(HashSet::new()),
+            layout: VertexLayout::standard(),
+            defines: Vec::new(),
+        }
+    }
+
+    /// Remember the extra defines the sources were preprocessed with, for `Renderer::reload_shader`.
+    pub fn with_defines(mut self, defines: &[(&str, &str)]) -> Shader {
+        self.defines = defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        self
+    }
+
+    pub fn get_defines(&self) -> Vec<(&str, &str)> {
+        self.defines.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+    }
+
+    pub fn get_layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    /// Replace the program with one built from new preprocessed sources, keeping the current program
+    /// if they fail to compile or link. Uniform values are not carried over, and the shader has to be
+    /// bound again for draws to use the new program.
+    pub fn reload(&self, vert_shader: &ShaderSource, frag_shader: &ShaderSource) -> Result<(), RenderError> {
+        match &self.backend {
+            ShaderBackend::Gl { program, gl } => {
+                let new_program = build_program(gl, vert_shader, frag_shader, &self.layout)?;
+                *self.attributes.borrow_mut() = Rc::new(active_attributes(gl, &new_program));
+                *self.reflection.borrow_mut() = Reflection::from_program(gl, &new_program);
+                gl.delete_program(Some(&program.replace(new_program)));
+                deleted(ResourceKind::Program);
+            }
+            ShaderBackend::Soft(program) => {
+                let (vertex, fragment) = (vert_shader.get_code(), frag_shader.get_code());
+                *program.borrow_mut() = Rc::new(SoftProgram::from_source(vertex, fragment));
+                *self.reflection.borrow_mut() = Reflection::from_source(vertex, fragment);
+            }
+        }
+
+        // Locations and values belong to the old program, and what was missing may exist now
+        self.uniforms.borrow_mut().clear();
+        self.values.borrow_mut().clear();
+        self.warned.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// The linked WebGL program, or `None` if this shader belongs to the software renderer.
+    pub fn program(&self) -> Option<WebGlProgram> {
+        match &self.backend {
+            ShaderBackend::Gl { program, .. } => Some(program.borrow().clone()),
+            ShaderBackend::Soft(_) => None,
+        }
+    }
+
+    pub fn soft_program(&self) -> Option<Rc<SoftProgram>> {
+        match &self.backend {
+            ShaderBackend::Gl { .. } => None,
+            ShaderBackend::Soft(program) => Some(program.borrow().clone()),
+        }
+    }
+
+    /// Where each attribute the program actually uses ended up after linking.
+    pub fn attribute_locations(&self) -> Rc<HashMap<String, u32>> {
+        self.attributes.borrow().clone()
+    }
+
+    pub fn get_attribute_location(&self, name: &str) -> Option<u32> {
+        self.attributes.borrow().get(name).copied()
+    }
+
+    /// The active uniforms and attributes, for listing or tweaking them.
+    pub fn get_reflection(&self) -> Ref<'_, Reflection> {
+        self.reflection.borrow()
+    }
+
+    pub fn get_active_uniforms(&self) -> Ref<'_, Vec<ShaderVariable>> {
+        Ref::map(self.reflection.borrow(), |r| r.get_uniforms())
+    }
+
+    pub fn get_active_attributes(&self) -> Ref<'_, Vec<ShaderVariable>> {
+        Ref::map(self.reflection.borrow(), |r| r.get_attributes())
+    }
+
+    /// Whether setting the named uniform would reach the shader.
+    /// Check this first to set uniforms a shader may not declare without a warning.
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.reflection.borrow().get_uniform(name).is_some()
+    }
+
+    /// Like `get_attribute_location`, for attributes that can't be done without.
+    pub fn require_attribute(&self, name: &str) -> Result<u32, RenderError> {
+        self.get_attribute_location(name).ok_or_else(|| RenderError::MissingAttribute(name.to_string()))
+    }
+
+    /// Read the named uniform block from a `UniformBuffer` attached to `binding`, WebGL 2 only.
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) -> Result<(), RenderError> {
+        let unsupported = || RenderError::Unsupported(String::from("uniform blocks need WebGL 2"));
+        let (program, gl) = match &self.backend {
+            ShaderBackend::Gl { program, gl } => (program.borrow(), gl.dyn_ref::<WebGl2RenderingContext>().ok_or_else(unsupported)?),
+            ShaderBackend::Soft(_) => return Err(unsupported()),
+        };
+
+        let index = gl.get_uniform_block_index(&program, name);
+        if index == WebGl2RenderingContext::INVALID_INDEX {
+            return Err(RenderError::MissingUniform(name.to_string()));
+        }
+
+        gl.uniform_block_binding(&program, index, binding);
+        Ok(())
+    }
+
+    /// Forget the values uploaded so far so the next set of each uniform reaches GL,
+    /// for when they have been changed without going through this shader.
+    pub fn clear_uniform_cache(&self) {
+        self.values.borrow_mut().clear();
     }
 
     /// Get the location of a uniform.
     /// If this is our first time retrieving it we will cache it so that for future retrievals
     /// we won't need to query the shader program.
     pub fn get_uniform_location(&self, uniform_name: &str) -> Option<WebGlUniformLocation> {
+        let (program, gl) = match &self.backend {
+            ShaderBackend::Gl { program, gl } => (program.borrow(), gl),
+            ShaderBackend::Soft(_) => return None,
+        };
+
         let mut uniforms = self.uniforms.borrow_mut();
 
         if uniforms.get(uniform_name).is_none() {
-            match self.gl.get_uniform_location(&self.program, uniform_name) {
-                Some(loc) => uniforms.insert(uniform_name.to_string(), loc),
-                None => None
-            };
+            if let Some(loc) = gl.get_uniform_location(&program, uniform_name) {
+                uniforms.insert(uniform_name.to_string(), loc);
+            }
         }
 
-        uniforms.get(uniform_name).map(|l| l.clone())
+        uniforms.get(uniform_name).cloned()
     }
 
-    pub fn set_uniform1f(&self, name: &str, x: f32) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform1f(Some(&location), x);
-        Ok(())
+    /// Set a uniform from any value of the matching type, such as an `f32` for a `float` or a `Matrix4` for a `mat4`.
+    pub fn set_uniform<T: UniformValue>(&self, name: &str, val: T) -> Result<(), RenderError> {
+        self.set_uniform_data(name, val.to_uniform_data())
+    }
+
+    /// Set consecutive elements of an array uniform in one go, starting from the first or,
+    /// for a name such as `lightColor[2]`, from the element it indexes.
+    pub fn set_uniform_array<T: UniformValue>(&self, name: &str, values: &[T]) -> Result<(), RenderError> {
+        let values: Vec<UniformData> = values.iter().map(|v| v.to_uniform_data()).collect();
+        self.set_uniform_data_array(name, &values)
+    }
+
+    pub fn set_uniform1i(&self, name: &str, x: i32) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Int(x))
+    }
+
+    pub fn set_uniform2i(&self, name: &str, val: Vector2<i32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::IVec2(val))
+    }
+
+    pub fn set_uniform3i(&self, name: &str, val: Vector3<i32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::IVec3(val))
     }
 
-    pub fn set_uniform1f_vec(&self, name: &str, val: Vector1<f32>) -> Result<(), ()> {
+    pub fn set_uniform4i(&self, name: &str, val: Vector4<i32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::IVec4(val))
+    }
+
+    pub fn set_uniform_bool(&self, name: &str, b: bool) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Bool(b))
+    }
+
+    /// Point a sampler at a texture unit, without binding anything to it. See `set_texture`.
+    pub fn set_uniform_sampler(&self, name: &str, unit: u32) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Sampler(unit))
+    }
+
+    pub fn set_uniform1f(&self, name: &str, x: f32) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Float(x))
+    }
+
+    pub fn set_uniform1f_vec(&self, name: &str, val: Vector1<f32>) -> Result<(), RenderError> {
         self.set_uniform1f(name, val[0])
     }
 
-    pub fn set_uniform2f(&self, name: &str, val: Vector2<f32>) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform2fv_with_f32_array(Some(&location), val.as_slice());
-        Ok(())
+    pub fn set_uniform2f(&self, name: &str, val: Vector2<f32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Vec2(val))
     }
 
-    pub fn set_uniform3f(&self, name: &str, val: Vector3<f32>) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform3fv_with_f32_array(Some(&location), val.as_slice());
-        Ok(())
+    pub fn set_uniform3f(&self, name: &str, val: Vector3<f32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Vec3(val))
     }
 
-    pub fn set_uniform4f(&self, name: &str, val: Vector4<f32>) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform4fv_with_f32_array(Some(&location), val.as_slice());
-        Ok(())
+    pub fn set_uniform4f(&self, name: &str, val: Vector4<f32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Vec4(val))
     }
 
-    pub fn set_uniform_matrix2f(&self, name: &str, val: Matrix2<f32>) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform_matrix2fv_with_f32_array(Some(&location), false, val.as_slice());
-        Ok(())
+    pub fn set_uniform_matrix2f(&self, name: &str, val: Matrix2<f32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Mat2(val))
     }
     
-    pub fn set_uniform_matrix3f(&self, name: &str, val: Matrix3<f32>) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform_matrix3fv_with_f32_array(Some(&location), false, val.as_slice());
-        Ok(())
+    pub fn set_uniform_matrix3f(&self, name: &str, val: Matrix3<f32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Mat3(val))
     }
     
-    pub fn set_uniform_matrix4f(&self, name: &str, val: Matrix4<f32>) -> Result<(), ()> {
-        let location = self.get_uniform_location(name).ok_or(())?;
-        self.gl.uniform_matrix4fv_with_f32_array(Some(&location), false, val.as_slice());
+    pub fn set_uniform_matrix4f(&self, name: &str, val: Matrix4<f32>) -> Result<(), RenderError> {
+        self.set_uniform_data(name, UniformData::Mat4(val))
+    }
+
+    pub fn set_uniform1i_array(&self, name: &str, values: &[i32]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    pub fn set_uniform1f_array(&self, name: &str, values: &[f32]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    pub fn set_uniform2f_array(&self, name: &str, values: &[Vector2<f32>]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    pub fn set_uniform3f_array(&self, name: &str, values: &[Vector3<f32>]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    pub fn set_uniform4f_array(&self, name: &str, values: &[Vector4<f32>]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    pub fn set_uniform_matrix3f_array(&self, name: &str, values: &[Matrix3<f32>]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    pub fn set_uniform_matrix4f_array(&self, name: &str, values: &[Matrix4<f32>]) -> Result<(), RenderError> {
+        self.set_uniform_array(name, values)
+    }
+
+    /// Bind a texture to a texture unit and point a sampler uniform at it.
+    pub fn set_texture(&self, name: &str, texture: &Texture, unit: u32) -> Result<(), RenderError> {
+        match &self.backend {
+            ShaderBackend::Gl { .. } => texture.bind(unit),
+            ShaderBackend::Soft(program) => {
+                let texture = texture.soft_texture().ok_or_else(|| RenderError::Texture(String::from("WebGL textures can't be sampled by software shaders")))?;
+                program.borrow().bind_texture(unit, texture.clone());
+            }
+        }
+
+        self.set_uniform_data(name, UniformData::Sampler(unit))
+    }
+
+    /// Upload a uniform value to the GL program, or record it on the software program.
+    /// Nothing is uploaded when the uniform already holds the value.
+    /// Fails if the shader has no such active uniform or it is of another type, warning the first time.
+    pub fn set_uniform_data(&self, name: &str, val: UniformData) -> Result<(), RenderError> {
+        self.validate_uniform(name, &val)?;
+
+        let key = self.cache_key(name);
+        if self.values.borrow().get(&key) == Some(&val) {
+            return Ok(());
+        }
+
+        match &self.backend {
+            ShaderBackend::Gl { gl, .. } => {
+                let location = self.get_uniform_location(name).ok_or_else(|| RenderError::MissingUniform(name.to_string()))?;
+                upload(gl, &location, &[val]);
+            }
+            ShaderBackend::Soft(program) => program.borrow().set_uniform(name, val),
+        }
+        self.values.borrow_mut().insert(key, val);
         Ok(())
     }
+
+    /// Upload the elements of an array uniform with one call, or record each on the software program.
+    /// Every value has to be of the same type, and the array long enough to hold them all.
+    pub fn set_uniform_data_array(&self, name: &str, values: &[UniformData]) -> Result<(), RenderError> {
+        let first = match values.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        let (base, start) = split_index(name).ok_or_else(|| RenderError::MissingUniform(name.to_string()))?;
+        let start = start.unwrap_or(0);
+
+        for (i, val) in values.iter().enumerate() {
+            let element = format!("{}[{}]", base, start + i as i32);
+            if std::mem::discriminant(val) != std::mem::discriminant(first) {
+                return Err(RenderError::UniformType {
+                    name: element,
+                    expected: uniform_data_type(first),
+                    found: uniform_data_type(val),
+                });
+            }
+            // Checking the last element also catches arrays too short for every value
+            if i == 0 || i == values.len() - 1 {
+                self.validate_uniform(&element, val)?;
+            }
+        }
+
+        let keys: Vec<String> = (0..values.len()).map(|i| format!("{}[{}]", base, start + i as i32)).collect();
+        let unchanged = {
+            let cached = self.values.borrow();
+            keys.iter().zip(values).all(|(key, val)| cached.get(key) == Some(val))
+        };
+        if unchanged {
+            return Ok(());
+        }
+
+        match &self.backend {
+            ShaderBackend::Gl { gl, .. } => {
+                let location = self.get_uniform_location(name).ok_or_else(|| RenderError::MissingUniform(name.to_string()))?;
+                upload(gl, &location, values);
+            }
+            ShaderBackend::Soft(program) => {
+                let program = program.borrow();
+                for (key, val) in keys.iter().zip(values) {
+                    program.set_uniform(key, *val);
+                }
+            }
+        }
+        self.values.borrow_mut().extend(keys.into_iter().zip(values.iter().copied()));
+        Ok(())
+    }
+
+    /// The name a uniform's value is cached under, the first element for an array set without an index.
+    fn cache_key(&self, name: &str) -> String {
+        match split_index(name) {
+            Some((base, None)) if self.reflection.borrow().get_uniform(base).is_some_and(|u| u.is_array()) => format!("{}[0]", base),
+            _ => name.to_string(),
+        }
+    }
+
+    fn validate_uniform(&self, name: &str, val: &UniformData) -> Result<(), RenderError> {
+        let err = match self.reflection.borrow().get_uniform(name) {
+            Some(uniform) if uniform.accepts(val) => return Ok(()),
+            Some(uniform) => RenderError::UniformType {
+                name: name.to_string(),
+                expected: uniform.type_name(),
+                found: uniform_data_type(val),
+            },
+            None => RenderError::MissingUniform(name.to_string()),
+        };
+
+        if self.warned.borrow_mut().insert(name.to_string()) {
+            console_log!("{}", err);
+        }
+        Err(err)
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        if let ShaderBackend::Gl { program, gl } = &self.backend {
+            gl.delete_program(Some(&program.borrow()));
+            deleted(ResourceKind::Program);
+        }
+    }
+}
+
+/// Upload values of one type to consecutive uniform locations starting at `location`.
+fn upload(gl: &WebGlRenderingContext, location: &WebGlUniformLocation, values: &[UniformData]) {
+    let location = Some(location);
+    if let Some(first) = values.first().and_then(|v| v.as_floats()) {
+        let floats: Vec<f32> = values.iter().filter_map(|v| v.as_floats()).flatten().copied().collect();
+        match first.len() {
+            1 => gl.uniform1fv_with_f32_array(location, &floats),
+            2 => gl.uniform2fv_with_f32_array(location, &floats),
+            3 => gl.uniform3fv_with_f32_array(location, &floats),
+            4 if matches!(values[0], UniformData::Mat2(_)) => gl.uniform_matrix2fv_with_f32_array(location, false, &floats),
+            4 => gl.uniform4fv_with_f32_array(location, &floats),
+            9 => gl.uniform_matrix3fv_with_f32_array(location, false, &floats),
+            _ => gl.uniform_matrix4fv_with_f32_array(location, false, &floats),
+        }
+        return;
+    }
+
+    let ints: Vec<i32> = values.iter().filter_map(|v| v.as_ints()).flatten().collect();
+    match values[0] {
+        UniformData::IVec2(_) => gl.uniform2iv_with_i32_array(location, &ints),
+        UniformData::IVec3(_) => gl.uniform3iv_with_i32_array(location, &ints),
+        UniformData::IVec4(_) => gl.uniform4iv_with_i32_array(location, &ints),
+        _ => gl.uniform1iv_with_i32_array(location, &ints),
+    }
+}
+
+/// Compile both stages and link them, with the attributes of `layout` bound to its locations.
+fn build_program(
+    context: &WebGlRenderingContext,
+    vert_shader: &ShaderSource,
+    frag_shader: &ShaderSource,
+    layout: &VertexLayout,
+) -> Result<WebGlProgram, RenderError> {
+    let vert_shader = compile_shader(context, ShaderStage::Vertex, vert_shader)?;
+    let frag_shader = match compile_shader(context, ShaderStage::Fragment, frag_shader) {
+        Ok(shader) => shader,
+        Err(err) => {
+            context.delete_shader(Some(&vert_shader));
+            return Err(err);
+        }
+    };
+
+    // The program keeps what it needs from the stages once linked, so they are freed either way
+    let program = link_program(context, &vert_shader, &frag_shader, layout);
+    context.delete_shader(Some(&vert_shader));
+    context.delete_shader(Some(&frag_shader));
+    program
 }
 
 fn compile_shader(
     context: &WebGlRenderingContext,
-    shader_type: u32,
-    source: &str,
-) -> Result<WebGlShader, String> {
+    stage: ShaderStage,
+    source: &ShaderSource,
+) -> Result<WebGlShader, RenderError> {
+    let shader_type = match stage {
+        ShaderStage::Vertex => WebGlRenderingContext::VERTEX_SHADER,
+        ShaderStage::Fragment => WebGlRenderingContext::FRAGMENT_SHADER,
+    };
     let shader = context
         .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-    context.shader_source(&shader, source);
+        .ok_or_else(|| RenderError::ContextUnavailable(String::from("Unable to create shader object")))?;
+    context.shader_source(&shader, source.get_code());
     context.compile_shader(&shader);
 
     if context
@@ -111,9 +630,11 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(context
+        let log = context
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        context.delete_shader(Some(&shader));
+        Err(RenderError::compile(stage, log, source))
     }
 }
 
@@ -121,13 +642,20 @@ fn link_program(
     context: &WebGlRenderingContext,
     vert_shader: &WebGlShader,
     frag_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
+    layout: &VertexLayout,
+) -> Result<WebGlProgram, RenderError> {
     let program = context
         .create_program()
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+        .ok_or_else(|| RenderError::ContextUnavailable(String::from("Unable to create program object")))?;
 
     context.attach_shader(&program, vert_shader);
     context.attach_shader(&program, frag_shader);
+
+    // Pin the layout's attributes to their slots, anything else is placed by the driver
+    for (location, attribute) in layout.get_attributes().iter().enumerate() {
+        context.bind_attrib_location(&program, location as u32, &attribute.name);
+    }
+
     context.link_program(&program);
 
     if context
@@ -135,10 +663,75 @@ fn link_program(
         .as_bool()
         .unwrap_or(false)
     {
+        created(ResourceKind::Program);
         Ok(program)
     } else {
-        Err(context
+        let log = context
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+            .unwrap_or_else(|| String::from("Unknown error creating program object"));
+        context.delete_program(Some(&program));
+        Err(RenderError::ShaderLink(log))
+    }
+}
+
+/// Look up the location of every attribute the linked program uses.
+fn active_attributes(context: &WebGlRenderingContext, program: &WebGlProgram) -> HashMap<String, u32> {
+    let count = context
+        .get_program_parameter(program, WebGlRenderingContext::ACTIVE_ATTRIBUTES)
+        .as_f64()
+        .unwrap_or(0.0) as u32;
+
+    (0..count)
+        .filter_map(|i| context.get_active_attrib(program, i))
+        .filter_map(|info| {
+            let location = context.get_attrib_location(program, &info.name());
+            (location >= 0).then(|| (info.name(), location as u32))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+    use crate::render::Renderer;
+    use crate::render::soft::SoftRenderer;
+
+    const FRAGMENT: &str = "precision mediump float;\nuniform vec4 tint;\nuniform vec4 lightColor[2];\nvoid main() {\n    gl_FragColor = tint * lightColor[0] * lightColor[1];\n}\n";
+
+    #[test]
+    fn skips_unchanged_uniforms() {
+        let render = SoftRenderer::new(1, 1);
+        let shader = render.create_shader(SHADER_SIMPLE_VERT, FRAGMENT).unwrap();
+        let program = shader.soft_program().unwrap();
+        let (red, blue) = (UniformData::Vec4(vector!(1.0, 0.0, 0.0, 1.0)), UniformData::Vec4(vector!(0.0, 0.0, 1.0, 1.0)));
+
+        // Changed behind the shader's back, so only a write that isn't skipped shows up
+        shader.set_uniform_data("tint", red).unwrap();
+        program.set_uniform("tint", blue);
+        shader.set_uniform_data("tint", red).unwrap();
+        assert_eq!(program.get_uniform("tint"), Some(blue));
+
+        shader.clear_uniform_cache();
+        shader.set_uniform_data("tint", red).unwrap();
+        assert_eq!(program.get_uniform("tint"), Some(red));
+    }
+
+    #[test]
+    fn skips_unchanged_arrays() {
+        let render = SoftRenderer::new(1, 1);
+        let shader = render.create_shader(SHADER_SIMPLE_VERT, FRAGMENT).unwrap();
+        let program = shader.soft_program().unwrap();
+        let (red, blue) = (UniformData::Vec4(vector!(1.0, 0.0, 0.0, 1.0)), UniformData::Vec4(vector!(0.0, 0.0, 1.0, 1.0)));
+
+        shader.set_uniform_data_array("lightColor", &[red, red]).unwrap();
+        program.set_uniform("lightColor[1]", blue);
+        shader.set_uniform_data("lightColor[1]", red).unwrap();
+        assert_eq!(program.get_uniform("lightColor[1]"), Some(blue));
+
+        // One element differing is enough to write the whole array again
+        shader.set_uniform_data_array("lightColor", &[blue, red]).unwrap();
+        assert_eq!(program.get_uniform("lightColor[0]"), Some(blue));
+        assert_eq!(program.get_uniform("lightColor[1]"), Some(red));
     }
 }
@@ -0,0 +1,11 @@
+precision mediump float;
+
+attribute vec4 position;
+attribute vec2 texcoord;
+
+varying vec2 frag_texcoord;
+
+void main() {
+    gl_Position = position;
+    frag_texcoord = texcoord;
+}
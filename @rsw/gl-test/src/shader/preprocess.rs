@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+use crate::render::error::{RenderError, ShaderStage};
+use crate::render::gl2::GlBackend;
+use crate::render::light::MAX_LIGHTS;
+use crate::render::shadow::{MAX_PCF_RADIUS, MAX_SHADOWS};
+
+/// Chunks every `Preprocessor` starts with, by the name they are included as.
+pub const BUILTIN_CHUNKS: &[(&str, &str)] = &[
+    ("lighting", include_str!("./chunk/lighting.glsl")),
+    ("shadows", include_str!("./chunk/shadows.glsl")),
+];
+
+/// Shader source after preprocessing, remembering where each line came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderSource {
+    code: String,
+    /// The file and line within it of each output line, `None` for generated lines.
+    origins: Vec<Option<(String, u32)>>,
+}
+
+impl ShaderSource {
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
+
+    /// The file and line a line of the output, counting from 1 as GLSL logs do, came from.
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = self.origins.get((line as usize).checked_sub(1)?)?.as_ref()?;
+        Some((file.as_str(), *line))
+    }
+
+    fn push(&mut self, line: &str, origin: Option<(&str, u32)>) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.origins.push(origin.map(|(file, line)| (file.to_string(), line)));
+    }
+}
+
+/// Expands `#include "name"` against a registry of chunks and puts `#define`s set from Rust
+/// ahead of the source, after its `#version` if it has one. `WEBGL2` is defined when compiling
+/// for WebGL 2, for shaders that want to use its features where available.
+///
+/// Each chunk is included at most once per shader, so chunks can include what they depend on.
+/// Defines are plain text, chunks guard theirs with `#ifndef` so they can be overridden.
+#[derive(Debug, Clone)]
+pub struct Preprocessor {
+    chunks: HashMap<String, String>,
+    defines: Vec<(String, String)>,
+}
+
+impl Preprocessor {
+    /// The built-in chunks, with `MAX_LIGHTS`, `MAX_SHADOWS` and `MAX_PCF_RADIUS` matching the renderer's limits.
+    pub fn new() -> Preprocessor {
+        let mut preprocessor = Preprocessor { chunks: HashMap::new(), defines: Vec::new() };
+        for (name, source) in BUILTIN_CHUNKS {
+            preprocessor.add_chunk(name, source);
+        }
+        preprocessor.define("MAX_LIGHTS", &MAX_LIGHTS.to_string());
+        preprocessor.define("MAX_SHADOWS", &MAX_SHADOWS.to_string());
+        preprocessor.define("MAX_PCF_RADIUS", &MAX_PCF_RADIUS.to_string());
+        preprocessor
+    }
+
+    /// Register a chunk for shaders to include, replacing any chunk of the same name.
+    pub fn add_chunk(&mut self, name: &str, source: &str) {
+        self.chunks.insert(name.to_string(), source.to_string());
+    }
+
+    pub fn get_chunk(&self, name: &str) -> Option<&str> {
+        self.chunks.get(name).map(|s| s.as_str())
+    }
+
+    /// Define a macro for every shader, replacing an earlier value.
+    pub fn define(&mut self, name: &str, value: &str) {
+        match self.defines.iter_mut().find(|(n, _)| n == name) {
+            Some(define) => define.1 = value.to_string(),
+            None => self.defines.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.defines.retain(|(n, _)| n != name);
+    }
+
+    pub fn get_defines(&self) -> &Vec<(String, String)> {
+        &self.defines
+    }
+
+    /// Preprocess the source of one stage, `name` being what error messages call it.
+    pub fn process(&self, name: &str, source: &str, stage: ShaderStage, backend: GlBackend) -> Result<ShaderSource, RenderError> {
+        let mut output = ShaderSource { code: String::new(), origins: Vec::new() };
+
+        // The version has to come before anything else, defines included
+        let version = source.lines().enumerate().find(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with("//"))
+            .filter(|(_, line)| line.trim().starts_with("#version"));
+        if let Some((index, line)) = version {
+            if line.contains("300 es") && backend == GlBackend::WebGl1 {
+                return Err(RenderError::compile_at(stage, format!("{} needs WebGL 2", line.trim()), name, index as u32 + 1, line));
+            }
+            output.push(line, Some((name, index as u32 + 1)));
+        }
+
+        if backend == GlBackend::WebGl2 {
+            output.push("#define WEBGL2", None);
+        }
+        for (define, value) in &self.defines {
+            output.push(&format!("#define {} {}", define, value), None);
+        }
+
+        let mut expansion = Expansion { stage, included: HashSet::new(), stack: vec![name.to_string()], output };
+        let skip = version.map(|(index, _)| index);
+        self.expand(name, source, skip, &mut expansion)?;
+        Ok(expansion.output)
+    }
+
+    /// Preprocess both stages of a program, with `defines` added on top of the preprocessor's own.
+    pub fn process_program(
+        &self,
+        vertex: &str,
+        fragment: &str,
+        defines: &[(&str, &str)],
+        backend: GlBackend,
+    ) -> Result<(ShaderSource, ShaderSource), RenderError> {
+        let mut extended;
+        let preprocessor = if defines.is_empty() {
+            self
+        } else {
+            extended = self.clone();
+            for (name, value) in defines {
+                extended.define(name, value);
+            }
+            &extended
+        };
+
+        Ok((
+            preprocessor.process("vertex", vertex, ShaderStage::Vertex, backend)?,
+            preprocessor.process("fragment", fragment, ShaderStage::Fragment, backend)?,
+        ))
+    }
+
+    fn expand(&self, name: &str, source: &str, skip: Option<usize>, expansion: &mut Expansion) -> Result<(), RenderError> {
+        for (index, line) in source.lines().enumerate() {
+            let number = index as u32 + 1;
+            if Some(index) == skip {
+                continue;
+            }
+
+            let chunk = match include_name(line) {
+                Some(chunk) => chunk,
+                None => {
+                    expansion.output.push(line, Some((name, number)));
+                    continue;
+                }
+            };
+
+            let stage = expansion.stage;
+            if expansion.stack.iter().any(|n| n == chunk) {
+                return Err(RenderError::compile_at(stage, format!("`{}` includes itself", chunk), name, number, line));
+            }
+            if !expansion.included.insert(chunk.to_string()) {
+                continue;
+            }
+
+            let chunk_source = self.chunks.get(chunk)
+                .ok_or_else(|| RenderError::compile_at(stage, format!("No shader chunk named `{}`", chunk), name, number, line))?;
+            expansion.stack.push(chunk.to_string());
+            self.expand(chunk, chunk_source, None, expansion)?;
+            expansion.stack.pop();
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Preprocessor::new()
+    }
+}
+
+/// State carried through nested includes.
+struct Expansion {
+    stage: ShaderStage,
+    /// Chunks already expanded, which are skipped if included again.
+    included: HashSet<String>,
+    /// The file being expanded and those that included it, to catch include cycles.
+    stack: Vec<String>,
+    output: ShaderSource,
+}
+
+/// The chunk named by an `#include "name"` or `#include <name>` line.
+fn include_name(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+    let name = rest.strip_prefix('"').and_then(|r| r.strip_suffix('"'))
+        .or_else(|| rest.strip_prefix('<').and_then(|r| r.strip_suffix('>')))?;
+    Some(name.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocessor() -> Preprocessor {
+        let mut preprocessor = Preprocessor { chunks: HashMap::new(), defines: Vec::new() };
+        preprocessor.add_chunk("common", "float common;");
+        preprocessor.add_chunk("lights", "#include \"common\"\nfloat lights;");
+        preprocessor
+    }
+
+    fn process(preprocessor: &Preprocessor, source: &str) -> Result<ShaderSource, RenderError> {
+        preprocessor.process("test", source, ShaderStage::Fragment, GlBackend::WebGl1)
+    }
+
+    #[test]
+    fn includes_each_chunk_once() {
+        let source = process(&preprocessor(), "#include \"lights\"\n#include <common>\nvoid main() {}").unwrap();
+        assert_eq!(source.get_code(), "float common;\nfloat lights;\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn maps_lines_to_their_file() {
+        let mut preprocessor = preprocessor();
+        preprocessor.define("COUNT", "4");
+        let source = process(&preprocessor, "#version 100\n#include \"lights\"\nvoid main() {}").unwrap();
+
+        assert_eq!(source.get_code().lines().collect::<Vec<_>>(), ["#version 100", "#define COUNT 4", "float common;", "float lights;", "void main() {}"]);
+        assert_eq!(source.origin(1), Some(("test", 1)));
+        assert_eq!(source.origin(2), None);
+        assert_eq!(source.origin(3), Some(("common", 1)));
+        assert_eq!(source.origin(4), Some(("lights", 2)));
+        assert_eq!(source.origin(5), Some(("test", 3)));
+        assert_eq!(source.origin(6), None);
+    }
+
+    #[test]
+    fn rejects_include_cycles() {
+        let mut preprocessor = preprocessor();
+        preprocessor.add_chunk("a", "#include \"b\"");
+        preprocessor.add_chunk("b", "float b;\n#include \"a\"");
+
+        match process(&preprocessor, "#include \"a\"") {
+            Err(RenderError::ShaderCompile { file, line, log, .. }) => {
+                assert_eq!(file.as_deref(), Some("b"));
+                assert_eq!(line, Some(2));
+                assert!(log.contains("`a` includes itself"), "{}", log);
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_chunks() {
+        let err = process(&preprocessor(), "void main() {}\n#include \"nope\"").unwrap_err();
+        assert!(matches!(err, RenderError::ShaderCompile { line: Some(2), .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn compile_errors_point_at_chunks() {
+        let source = process(&preprocessor(), "#include \"lights\"\nvoid main() {}").unwrap();
+        let err = RenderError::compile(ShaderStage::Fragment, String::from("ERROR: 0:2: 'lights' : redefinition"), &source);
+        match err {
+            RenderError::ShaderCompile { file, line, source, log, .. } => {
+                assert_eq!(file.as_deref(), Some("lights"));
+                assert_eq!(line, Some(2));
+                assert_eq!(source.as_deref(), Some("float lights;"));
+                assert_eq!(log, "ERROR: lights:2: 'lights' : redefinition");
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use web_sys::{WebGlProgram, WebGlRenderingContext as Gl, WebGl2RenderingContext as Gl2};
+use crate::shader::UniformData;
+
+/// GLSL type names by the GL enum reported for them.
+const TYPES: &[(u32, &str)] = &[
+    (Gl::FLOAT, "float"),
+    (Gl::FLOAT_VEC2, "vec2"),
+    (Gl::FLOAT_VEC3, "vec3"),
+    (Gl::FLOAT_VEC4, "vec4"),
+    (Gl::INT, "int"),
+    (Gl::INT_VEC2, "ivec2"),
+    (Gl::INT_VEC3, "ivec3"),
+    (Gl::INT_VEC4, "ivec4"),
+    (Gl::BOOL, "bool"),
+    (Gl::BOOL_VEC2, "bvec2"),
+    (Gl::BOOL_VEC3, "bvec3"),
+    (Gl::BOOL_VEC4, "bvec4"),
+    (Gl::FLOAT_MAT2, "mat2"),
+    (Gl::FLOAT_MAT3, "mat3"),
+    (Gl::FLOAT_MAT4, "mat4"),
+    (Gl::SAMPLER_2D, "sampler2D"),
+    (Gl::SAMPLER_CUBE, "samplerCube"),
+    (Gl2::UNSIGNED_INT, "uint"),
+    (Gl2::UNSIGNED_INT_VEC2, "uvec2"),
+    (Gl2::UNSIGNED_INT_VEC3, "uvec3"),
+    (Gl2::UNSIGNED_INT_VEC4, "uvec4"),
+    (Gl2::SAMPLER_3D, "sampler3D"),
+    (Gl2::SAMPLER_2D_SHADOW, "sampler2DShadow"),
+    (Gl2::SAMPLER_2D_ARRAY, "sampler2DArray"),
+    (Gl2::SAMPLER_2D_ARRAY_SHADOW, "sampler2DArrayShadow"),
+    (Gl2::SAMPLER_CUBE_SHADOW, "samplerCubeShadow"),
+    (Gl2::INT_SAMPLER_2D, "isampler2D"),
+    (Gl2::INT_SAMPLER_3D, "isampler3D"),
+    (Gl2::INT_SAMPLER_CUBE, "isamplerCube"),
+    (Gl2::INT_SAMPLER_2D_ARRAY, "isampler2DArray"),
+    (Gl2::UNSIGNED_INT_SAMPLER_2D, "usampler2D"),
+    (Gl2::UNSIGNED_INT_SAMPLER_3D, "usampler3D"),
+    (Gl2::UNSIGNED_INT_SAMPLER_CUBE, "usamplerCube"),
+    (Gl2::UNSIGNED_INT_SAMPLER_2D_ARRAY, "usampler2DArray"),
+];
+
+/// An active uniform or attribute of a shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderVariable {
+    /// The name without any `[0]` GL adds to arrays.
+    pub name: String,
+    /// The GL enum for its type, such as `FLOAT_VEC3`.
+    pub gl_type: u32,
+    /// Number of elements, 1 unless it is an array.
+    pub size: i32,
+}
+
+impl ShaderVariable {
+    /// The GLSL name of the type, such as `vec3`.
+    pub fn type_name(&self) -> &'static str {
+        type_name(self.gl_type)
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.size > 1
+    }
+
+    pub fn is_sampler(&self) -> bool {
+        self.type_name().contains("sampler")
+    }
+
+    /// Whether `value` can be uploaded to this uniform. Bools take ints or floats and ints take bools,
+    /// as GL allows, while samplers take a texture unit as either an int or a `Sampler`.
+    pub fn accepts(&self, value: &UniformData) -> bool {
+        match value {
+            UniformData::Int(_) => self.gl_type == Gl::INT || self.gl_type == Gl::BOOL || self.is_sampler(),
+            UniformData::Bool(_) => self.gl_type == Gl::BOOL || self.gl_type == Gl::INT,
+            UniformData::Sampler(_) => self.is_sampler(),
+            UniformData::IVec2(_) => self.gl_type == Gl::INT_VEC2 || self.gl_type == Gl::BOOL_VEC2,
+            UniformData::IVec3(_) => self.gl_type == Gl::INT_VEC3 || self.gl_type == Gl::BOOL_VEC3,
+            UniformData::IVec4(_) => self.gl_type == Gl::INT_VEC4 || self.gl_type == Gl::BOOL_VEC4,
+            UniformData::Float(_) => self.gl_type == Gl::FLOAT || self.gl_type == Gl::BOOL,
+            UniformData::Vec2(_) => self.gl_type == Gl::FLOAT_VEC2,
+            UniformData::Vec3(_) => self.gl_type == Gl::FLOAT_VEC3,
+            UniformData::Vec4(_) => self.gl_type == Gl::FLOAT_VEC4,
+            UniformData::Mat2(_) => self.gl_type == Gl::FLOAT_MAT2,
+            UniformData::Mat3(_) => self.gl_type == Gl::FLOAT_MAT3,
+            UniformData::Mat4(_) => self.gl_type == Gl::FLOAT_MAT4,
+        }
+    }
+}
+
+/// The GLSL name of a GL type enum.
+pub fn type_name(gl_type: u32) -> &'static str {
+    TYPES.iter().find(|(t, _)| *t == gl_type).map(|(_, name)| *name).unwrap_or("unknown")
+}
+
+/// The GLSL type a value is uploaded as.
+pub fn uniform_data_type(value: &UniformData) -> &'static str {
+    match value {
+        UniformData::Int(_) => "int",
+        UniformData::Bool(_) => "bool",
+        UniformData::Sampler(_) => "sampler",
+        UniformData::IVec2(_) => "ivec2",
+        UniformData::IVec3(_) => "ivec3",
+        UniformData::IVec4(_) => "ivec4",
+        UniformData::Float(_) => "float",
+        UniformData::Vec2(_) => "vec2",
+        UniformData::Vec3(_) => "vec3",
+        UniformData::Vec4(_) => "vec4",
+        UniformData::Mat2(_) => "mat2",
+        UniformData::Mat3(_) => "mat3",
+        UniformData::Mat4(_) => "mat4",
+    }
+}
+
+/// Every active uniform and attribute of a shader, in the order GL lists them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reflection {
+    uniforms: Vec<ShaderVariable>,
+    attributes: Vec<ShaderVariable>,
+}
+
+impl Reflection {
+    /// Ask GL what survived linking. Anything the compiler found unused is left out.
+    pub fn from_program(gl: &Gl, program: &WebGlProgram) -> Reflection {
+        let count = |pname| gl.get_program_parameter(program, pname).as_f64().unwrap_or(0.0) as u32;
+
+        let uniforms = (0..count(Gl::ACTIVE_UNIFORMS))
+            .filter_map(|i| gl.get_active_uniform(program, i))
+            .map(|info| variable(&info.name(), info.type_(), info.size()))
+            .collect();
+        let attributes = (0..count(Gl::ACTIVE_ATTRIBUTES))
+            .filter_map(|i| gl.get_active_attrib(program, i))
+            .map(|info| variable(&info.name(), info.type_(), info.size()))
+            .collect();
+
+        Reflection { uniforms, attributes }
+    }
+
+    /// Read the declarations out of preprocessed sources, for backends without GL to ask.
+    /// Unlike GL this includes anything declared but unused.
+    pub fn from_source(vertex: &str, fragment: &str) -> Reflection {
+        let mut reflection = Reflection::default();
+        for (source, is_vertex) in [(vertex, true), (fragment, false)] {
+            let defines = defines(source);
+            for (qualifier, variable) in declarations(source, &defines) {
+                let list = match qualifier {
+                    "uniform" => &mut reflection.uniforms,
+                    // Inputs to the fragment stage are varyings
+                    _ if is_vertex => &mut reflection.attributes,
+                    _ => continue,
+                };
+                // Both stages may declare the same uniform
+                if !list.iter().any(|v| v.name == variable.name) {
+                    list.push(variable);
+                }
+            }
+        }
+        reflection
+    }
+
+    pub fn get_uniforms(&self) -> &Vec<ShaderVariable> { &self.uniforms }
+    pub fn get_attributes(&self) -> &Vec<ShaderVariable> { &self.attributes }
+
+    /// The uniform a name sets, which may index into an array as in `lightColor[2]`.
+    pub fn get_uniform(&self, name: &str) -> Option<&ShaderVariable> {
+        let (base, index) = split_index(name)?;
+        self.uniforms.iter().find(|u| u.name == base && index.is_none_or(|i| i < u.size))
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&ShaderVariable> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+}
+
+fn variable(name: &str, gl_type: u32, size: i32) -> ShaderVariable {
+    ShaderVariable { name: name.strip_suffix("[0]").unwrap_or(name).to_string(), gl_type, size }
+}
+
+/// Split `name[3]` into `name` and `3`, `None` if the index is malformed.
+pub(crate) fn split_index(name: &str) -> Option<(&str, Option<i32>)> {
+    match name.strip_suffix(']').and_then(|n| n.split_once('[')) {
+        Some((base, index)) => Some((base, Some(index.parse().ok()?))),
+        None => Some((name, None)),
+    }
+}
+
+/// `#define NAME VALUE` lines, to work out array sizes given as macros.
+/// The first definition wins, later ones being fallbacks behind `#ifndef`.
+fn defines(source: &str) -> HashMap<&str, &str> {
+    let mut defines = HashMap::new();
+    for line in source.lines() {
+        let mut parts = match line.trim().strip_prefix("#define") {
+            Some(rest) => rest.split_whitespace(),
+            None => continue,
+        };
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            defines.entry(name).or_insert(value);
+        }
+    }
+    defines
+}
+
+/// `uniform`, `attribute` and `in` declarations, one variable per name in each.
+fn declarations<'a>(source: &'a str, defines: &HashMap<&str, &str>) -> Vec<(&'a str, ShaderVariable)> {
+    let mut found = Vec::new();
+    for line in source.lines() {
+        let mut words = line.trim().trim_end_matches(';').split_whitespace();
+        let qualifier = match words.next() {
+            Some(q @ ("uniform" | "attribute" | "in")) => q,
+            _ => continue,
+        };
+
+        // Skip precision qualifiers between the storage qualifier and the type
+        let mut words = words.skip_while(|w| matches!(*w, "lowp" | "mediump" | "highp"));
+        let gl_type = match words.next().and_then(|t| TYPES.iter().find(|(_, name)| *name == t)) {
+            Some((gl_type, _)) => *gl_type,
+            None => continue,
+        };
+
+        for declared in words.collect::<String>().split(',') {
+            let (name, size) = match declared.strip_suffix(']').and_then(|d| d.split_once('[')) {
+                Some((name, size)) => {
+                    let size = defines.get(size.trim()).copied().unwrap_or(size.trim());
+                    (name, size.parse().unwrap_or(1))
+                }
+                None => (declared, 1),
+            };
+            if !name.is_empty() {
+                found.push((qualifier, ShaderVariable { name: name.to_string(), gl_type, size }));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+
+    const VERTEX: &str = "#define LIGHTS 4\n\
+        attribute vec3 position;\n\
+        attribute highp vec4 color;\n\
+        uniform mat4 mvp, model;\n\
+        uniform vec4 lightColor[LIGHTS];\n\
+        varying vec4 vColor;\n\
+        void main() {}\n";
+    const FRAGMENT: &str = "precision mediump float;\n\
+        in vec4 vColor;\n\
+        uniform vec4 lightColor[LIGHTS];\n\
+        uniform sampler2D diffuseTexture;\n\
+        uniform bool receiveShadows;\n\
+        void main() {}\n";
+
+    #[test]
+    fn reads_declarations() {
+        let reflection = Reflection::from_source(VERTEX, FRAGMENT);
+
+        let names: Vec<_> = reflection.get_uniforms().iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(names, ["mvp", "model", "lightColor", "diffuseTexture", "receiveShadows"]);
+        let names: Vec<_> = reflection.get_attributes().iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["position", "color"]);
+
+        let lights = reflection.get_uniform("lightColor").unwrap();
+        assert_eq!((lights.type_name(), lights.size), ("vec4", 4));
+        assert!(reflection.get_uniform("diffuseTexture").unwrap().is_sampler());
+        assert_eq!(reflection.get_attribute("color").unwrap().type_name(), "vec4");
+    }
+
+    #[test]
+    fn finds_array_elements() {
+        let reflection = Reflection::from_source(VERTEX, FRAGMENT);
+        assert!(reflection.get_uniform("lightColor[3]").is_some());
+        assert!(reflection.get_uniform("lightColor[4]").is_none());
+        assert!(reflection.get_uniform("lightColor[x]").is_none());
+        assert!(reflection.get_uniform("vColor").is_none());
+    }
+
+    #[test]
+    fn accepts_matching_values() {
+        let reflection = Reflection::from_source(VERTEX, FRAGMENT);
+        let lights = reflection.get_uniform("lightColor").unwrap();
+        assert!(lights.accepts(&UniformData::Vec4(vector!(1.0, 1.0, 1.0, 1.0))));
+        assert!(!lights.accepts(&UniformData::Vec3(vector!(1.0, 1.0, 1.0))));
+
+        let shadows = reflection.get_uniform("receiveShadows").unwrap();
+        assert!(shadows.accepts(&UniformData::Bool(true)));
+        assert!(shadows.accepts(&UniformData::Int(1)));
+        assert!(reflection.get_uniform("diffuseTexture").unwrap().accepts(&UniformData::Sampler(0)));
+    }
+}
@@ -0,0 +1,15 @@
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+
+// Spread depth over all four channels, for shadow maps without a depth texture
+vec4 packDepth(float depth) {
+    vec4 bits = fract(depth * vec4(1.0, 255.0, 65025.0, 16581375.0));
+    return bits - bits.yzww * vec4(1.0 / 255.0, 1.0 / 255.0, 1.0 / 255.0, 0.0);
+}
+
+void main() {
+    gl_FragColor = packDepth(gl_FragCoord.z);
+}
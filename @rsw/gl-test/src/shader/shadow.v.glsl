@@ -0,0 +1,9 @@
+precision mediump float;
+
+attribute vec4 position;
+
+uniform mat4 mvp;
+
+void main() {
+    gl_Position = mvp * position;
+}
@@ -1,19 +1,16 @@
 precision mediump float;
 
+varying vec3 frag_position;
 varying vec3 frag_normal;
 varying vec4 frag_color;
 
 //uniform float time;
-uniform vec4 ambientLightColor;
-uniform vec4 directionalLightColor;
-uniform vec3 directionalLightDir;
+
+#include "lighting"
 
 void main() {
     //gl_FragColor = vec4(abs(sin(time)), 0.07, 0.73, 1.0);
 
-    float shadeFactor = max(0.0, dot(normalize(frag_normal), directionalLightDir));
-    vec3 irradiance = (ambientLightColor.rgb * ambientLightColor.a) + 
-        (directionalLightColor.rgb * directionalLightColor.a) * shadeFactor;
-
-    gl_FragColor = vec4(pow(frag_color.rgb * irradiance, vec3(1.0/2.2)), 1.0);
+    vec3 light = irradiance(frag_position, normalize(frag_normal));
+    gl_FragColor = vec4(pow(frag_color.rgb * light, vec3(1.0/2.2)), 1.0);
 }
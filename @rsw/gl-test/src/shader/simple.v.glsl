@@ -3,16 +3,22 @@ precision mediump float;
 attribute vec4 position;
 attribute vec3 normal;
 attribute vec4 color;
+attribute vec2 texcoord;
 
+varying vec3 frag_position;
 varying vec3 frag_normal;
 varying vec4 frag_color;
+varying vec2 frag_texcoord;
 
 uniform mat4 mvp;
+uniform mat4 model;
 uniform mat4 normalMatrix;
 uniform float time;
 
 void main() {
     gl_Position = mvp * position;
+    frag_position = (model * position).xyz;
     frag_normal = normalize(mat3(normalMatrix) * normal);
     frag_color = color;
+    frag_texcoord = texcoord;
 }
\ No newline at end of file
@@ -0,0 +1,17 @@
+precision mediump float;
+
+varying vec3 frag_position;
+varying vec3 frag_normal;
+varying vec2 frag_texcoord;
+
+uniform sampler2D diffuseTexture;
+
+#include "lighting"
+
+void main() {
+    vec4 texel = texture2D(diffuseTexture, frag_texcoord);
+    vec3 albedo = pow(texel.rgb, vec3(2.2));
+
+    vec3 light = irradiance(frag_position, normalize(frag_normal));
+    gl_FragColor = vec4(pow(albedo * light, vec3(1.0/2.2)), texel.a);
+}
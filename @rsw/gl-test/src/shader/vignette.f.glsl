@@ -0,0 +1,17 @@
+precision mediump float;
+
+varying vec2 frag_texcoord;
+
+uniform sampler2D source;
+uniform vec2 resolution;
+// How dark the corners get, from 0 to 1
+uniform float vignetteStrength;
+// Distance from the center, in screen heights, where the darkening starts
+uniform float vignetteRadius;
+
+void main() {
+    vec4 color = texture2D(source, frag_texcoord);
+    vec2 offset = (frag_texcoord - 0.5) * vec2(resolution.x / resolution.y, 1.0);
+    float vignette = smoothstep(vignetteRadius, vignetteRadius + 0.5, length(offset));
+    gl_FragColor = vec4(color.rgb * (1.0 - vignette * vignetteStrength), color.a);
+}
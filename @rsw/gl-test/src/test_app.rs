@@ -1,54 +1,91 @@
+use std::collections::HashMap;
+use std::rc::Rc;
 use web_sys::{WebGlRenderingContext, HtmlCanvasElement};
 use nalgebra::vector;
 use nalgebra::*;
 use wasm_bindgen::{JsCast, JsValue};
-use wasm_bindgen::prelude::*;
 use noise::{NoiseFn, Perlin, Seedable};
 
 use crate::app::Application;
-use crate::render::{Renderer, GlRenderer, mesh::Mesh, light::AmbientLight, light::DirectionalLight};
-use crate::shader::Shader;
-use crate::shader::{SHADER_SIMPLE_FRAG, SHADER_SIMPLE_VERT, SHADER_FLATCOLOR_FRAG};
-
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
-}
+use crate::input::InputState;
+use crate::render::{Renderer, GlRenderer, mesh::Mesh};
+use crate::render::camera::Camera;
+use crate::render::light::{AmbientLight, DirectionalLight, LightSet, MAX_LIGHTS};
+use crate::render::material::{Material, apply_transform};
+use crate::render::post::{PostChain, PostEffect};
+use crate::render::resource::BufferUsage;
+use crate::render::target::RenderTargetOptions;
+use crate::shader::{Shader, SHADER_SIMPLE_FRAG, SHADER_SIMPLE_VERT, SHADER_FLATCOLOR_FRAG};
+use crate::log;
 
 macro_rules! console_log { ($($t:tt)*) => (log(&format!("[test_app] {}", &format_args!($($t)*)).to_string())) }
 
-pub struct TestApplication {
-    render: GlRenderer,
+pub struct TestApplication<R: Renderer = GlRenderer> {
+    render: R,
     mesh: Mesh,
     computed_mesh: Mesh,
     outline_mesh: Mesh,
     time: f32,
-    program: Option<Shader>,
-    outline_program: Option<Shader>,
-    view: Matrix4<f32>,
-    projection: Matrix4<f32>,
-    ambient_light: AmbientLight,
-    dir_light: DirectionalLight,
+    material: Option<Material>,
+    outline_material: Option<Material>,
+    post: Option<PostChain>,
+    camera: Camera,
+    lights: LightSet,
     perlin: Perlin,
+    /// Shaders `reload_shader` can replace, by the name of their fragment source.
+    shaders: HashMap<&'static str, NamedShader>,
 }
 
-impl Application for TestApplication {
-    fn start(&mut self) -> Result<(), JsValue> {
-        self.program = self.render.create_shader(SHADER_SIMPLE_VERT, SHADER_SIMPLE_FRAG).ok();
-        self.outline_program = self.render.create_shader(SHADER_SIMPLE_VERT, SHADER_FLATCOLOR_FRAG).ok();
+/// A reloadable shader and the sources it was last built from.
+struct NamedShader {
+    shader: Rc<Shader>,
+    vertex: String,
+    fragment: String,
+}
 
-        if self.program.is_none() || self.outline_program.is_none() {
-            console_log!("Failed to compile shaders!");
-            panic!();
-        }
+impl<R: Renderer> Application for TestApplication<R> {
+    fn start(&mut self) -> Result<(), JsValue> {
+        let compiled = self.render.create_shader(SHADER_SIMPLE_VERT, SHADER_SIMPLE_FRAG)
+            .and_then(|program| Ok((program, self.render.create_shader(SHADER_SIMPLE_VERT, SHADER_FLATCOLOR_FRAG)?)));
+        let (program, outline_program) = match compiled {
+            Ok(programs) => programs,
+            Err(err) => {
+                console_log!("Failed to compile shaders! {}", err);
+                return Err(err.into());
+            }
+        };
 
         console_log!("Shader compiled!");
 
+        let program = Rc::new(program);
+        let outline_program = Rc::new(outline_program);
+        self.shaders.insert("simple", NamedShader { shader: program.clone(), vertex: SHADER_SIMPLE_VERT.to_string(), fragment: SHADER_SIMPLE_FRAG.to_string() });
+        self.shaders.insert("flatcolor", NamedShader { shader: outline_program.clone(), vertex: SHADER_SIMPLE_VERT.to_string(), fragment: SHADER_FLATCOLOR_FRAG.to_string() });
+
+        self.material = Some(Material::new(program));
+
+        let mut outline_material = Material::new(outline_program);
+        outline_material.set_vec4("flatColor", vector!(0.851, 0.149, 0.663, 1.0));
+        self.outline_material = Some(outline_material);
+
         self.render.enable_depth_test();
 
-        self.view = Matrix4::new_translation(&vector!(0.0, 0.0, -1.0));
-        self.projection = Matrix4::new_perspective(self.render.aspect(), 70.0, 0.1, 100.0);
+        // Make the outline glow. Without render targets the scene is drawn straight to the canvas instead.
+        let post = PostChain::new(&self.render, &RenderTargetOptions::default()).and_then(|mut post| {
+            post.push(PostEffect::bloom(&self.render)?);
+            post.push(PostEffect::vignette(&self.render)?);
+            Ok(post)
+        });
+        self.post = match post {
+            Ok(post) => Some(post),
+            Err(err) => {
+                console_log!("Post processing unavailable. {}", err);
+                None
+            }
+        };
+
+        self.camera.look_at(vector!(0.0, 0.0, 1.0), Vector3::zeros(), Vector3::y());
+        self.camera.update_aspect(&self.render);
 
         self.mesh.add_verticies(
             recursive_subdivide(
@@ -68,54 +105,90 @@ impl Application for TestApplication {
             )
         );
 
+        // Shared corners come out of the subdivision several times, only keep one of each
+        self.mesh.weld(0.00001);
+
+        // Both are rebuilt every frame
+        self.computed_mesh.usage = BufferUsage::Dynamic;
+        self.outline_mesh.usage = BufferUsage::Dynamic;
+
         //self.mesh.update_buffers(&self.render);
         console_log!("Application started.");
         Ok(())
     }
 
-    fn update(&mut self, dt: f32) {
+    fn update(&mut self, dt: f32, _input: &InputState) {
         self.time += dt;
-        self.projection = Matrix4::new_perspective(self.render.aspect(), 70.0, 0.01, 100.0);
+        self.camera.update_aspect(&self.render);
+        if let Some(post) = self.post.as_mut() {
+            post.update_size(&self.render);
+        }
 
         
-        self.computed_mesh = Mesh::new();
-        for vert in self.mesh.get_verticies() {
+        let displaced: Vec<Vector3<f32>> = self.mesh.get_verticies().iter().map(|vert| {
             let noise_in = (vert * 7.5) + vector!(128.0, 128.0, 0.0);
 
             let noise_out: Vector3<f32> = Vector3::new(0.0, 0.0,
                 0.1 * self.perlin.get([noise_in.x as f64, noise_in.y as f64, (self.time * 0.5) as f64]) as f32,
             );
 
-            self.computed_mesh.add_vertex(vert + noise_out);
-            self.computed_mesh.add_color(vector!(0.114, 0.137, 0.165, 1.0));
+            vert + noise_out
+        }).collect();
+
+        // Unroll the indicies again, every face gets its own normal.
+        // After the first frame only the positions move, so the colors are never uploaded again.
+        let indicies = self.mesh.get_indicies();
+        if self.computed_mesh.len() != indicies.len() {
+            self.computed_mesh.clear();
+            for index in indicies {
+                self.computed_mesh.add_vertex(displaced[*index as usize]);
+                self.computed_mesh.add_color(vector!(0.114, 0.137, 0.165, 1.0));
+            }
+        } else {
+            for (i, index) in indicies.iter().enumerate() {
+                self.computed_mesh.set_vertex(i, displaced[*index as usize]);
+            }
         }
 
         self.computed_mesh.use_colors = true;
 
         self.computed_mesh.generate_normals();
-        self.computed_mesh.update_buffers(&self.render);
+        if let Err(err) = self.computed_mesh.update_buffers(&self.render) {
+            console_log!("Unable to upload mesh. {}", err);
+        }
 
         // Create wireframe mesh of lines
-        self.outline_mesh = Mesh::new();
+        self.outline_mesh.clear();
         let v = self.computed_mesh.get_verticies();
         for i in (0..self.computed_mesh.len()).step_by(3) {
             let o = vector!(0.0, 0.0, 0.00005);
             self.outline_mesh.add_verticies(vec!(v[i]+o, v[i + 1]+o, v[i + 1]+o, v[i + 2]+o));
         }
         self.outline_mesh.draw_mode = WebGlRenderingContext::LINES;
-        self.outline_mesh.update_buffers(&self.render);
+        if let Err(err) = self.outline_mesh.update_buffers(&self.render) {
+            console_log!("Unable to upload mesh. {}", err);
+        }
     }
 
     fn render(&self) {
+        let mut globals = self.render.get_globals();
+        globals.time = self.time;
+        globals.set_camera(&self.camera);
+        globals.resolution = vector!(self.render.get_width() as f32, self.render.get_height() as f32);
+        self.render.set_globals(globals);
+
+        if let Some(post) = self.post.as_ref() {
+            post.begin(&self.render);
+        }
         self.render.clear(vector!(0.1, 0.1, 0.1, 1.0));
 
         // Find the upper right corner of screen
-        let vp = self.projection * self.view;
-        let vp_inv = vp.try_inverse().unwrap_or(vp.pseudo_inverse(0.000001).unwrap());
-        let upper_right = vp_inv * vector!(1.0, 1.0, 0.0, 0.0);
+        let upper_right = self.camera.ray_from_screen(vector!(1.0, 1.0))
+            .and_then(|ray| ray.intersect_plane(Vector3::zeros(), Vector3::z()))
+            .unwrap_or(vector!(1.0, 1.0, 0.0));
 
         // Rotate and scale model to fill screen
-        let angle: f32 = upper_right.xyz().angle(&vector!(0.0,1.0,0.0));
+        let angle: f32 = upper_right.angle(&vector!(0.0,1.0,0.0));
         let mut model = Matrix4::from_axis_angle(&Unit::new_normalize(vector!(0.0,0.0,-1.0)), angle);
         model = model * Matrix4::new_scaling(upper_right.magnitude() * 2.5); // extra 0.5
 
@@ -125,29 +198,59 @@ impl Application for TestApplication {
             model = model * Matrix4::new_scaling(1.0 + over_angle);
         }
         
-        let mvp = self.projection * self.view * model;
+        let view_projection = self.camera.view_projection();
 
         // Render mesh
-        self.render.set_shader(self.program.as_ref());
-        self.program.as_ref().unwrap().set_uniform_matrix4f("mvp", mvp);
-        self.program.as_ref().unwrap().set_uniform_matrix4f("normalMatrix", model.transpose().try_inverse().unwrap_or(Matrix4::identity()));
-        self.program.as_ref().unwrap().set_uniform1f("time", self.time);
-        self.program.as_ref().unwrap().set_uniform4f("ambientLightColor", self.ambient_light.color.push(self.ambient_light.intensity));
-        self.program.as_ref().unwrap().set_uniform4f("directionalLightColor", self.dir_light.color.push(self.dir_light.intensity));
-        self.program.as_ref().unwrap().set_uniform3f("directionalLightDir", self.dir_light.direction);
+        let material = self.material.as_ref().unwrap();
+        material.apply(&self.render).ok();
+        apply_transform(material.get_shader(), view_projection, model);
+        self.lights.apply(material.get_shader()).ok();
         self.render.draw_mesh(&self.computed_mesh);
 
         // Render the outline
-        self.render.set_shader(self.outline_program.as_ref());
-        self.outline_program.as_ref().unwrap().set_uniform_matrix4f("mvp", mvp);
-        self.outline_program.as_ref().unwrap().set_uniform4f("flatColor", vector!(0.851, 0.149, 0.663, 1.0));
+        let outline_material = self.outline_material.as_ref().unwrap();
+        outline_material.apply(&self.render).ok();
+        apply_transform(outline_material.get_shader(), view_projection, model);
         self.render.draw_mesh(&self.outline_mesh);
+
+        if let Some(post) = self.post.as_ref() {
+            post.end(&self.render);
+        }
     }
 
     fn exit(&self) {
         
     }
 
+    fn reload_shader(&mut self, name: &str, vertex: &str, fragment: &str) -> Result<(), JsValue> {
+        let named = self.shaders.get_mut(name).ok_or_else(|| JsValue::from_str(&format!("No shader named `{}`", name)))?;
+        if let Err(err) = self.render.reload_shader(&named.shader, vertex, fragment) {
+            console_log!("Failed to reload shader {}! {}", name, err);
+            return Err(err.into());
+        }
+
+        named.vertex = vertex.to_string();
+        named.fragment = fragment.to_string();
+        console_log!("Reloaded shader {}.", name);
+        Ok(())
+    }
+
+    fn reload_shader_chunk(&mut self, name: &str, source: &str) -> Result<(), JsValue> {
+        // Keep the new chunk even if it breaks a shader, fixing it will reload them again
+        self.render.get_preprocessor().borrow_mut().add_chunk(name, source);
+
+        let mut result = Ok(());
+        for (shader_name, named) in &self.shaders {
+            if let Err(err) = self.render.reload_shader(&named.shader, &named.vertex, &named.fragment) {
+                console_log!("Failed to reload shader {}! {}", shader_name, err);
+                result = result.and(Err(err));
+            }
+        }
+
+        console_log!("Reloaded shader chunk {}.", name);
+        result.map_err(JsValue::from)
+    }
+
     fn get_renderer(&self) -> &dyn Renderer {
         &self.render
     }
@@ -156,27 +259,39 @@ impl Application for TestApplication {
 impl TestApplication {
     pub fn new(canvas: HtmlCanvasElement) -> Result<Self, JsValue> {
         let render = GlRenderer::create(canvas)?;
-        Ok(TestApplication {
+        Ok(TestApplication::with_renderer(render))
+    }
+}
+
+impl<R: Renderer> TestApplication<R> {
+    /// Create the application on top of any renderer, such as a `SoftRenderer` for headless runs.
+    pub fn with_renderer(render: R) -> Self {
+        let mut lights = LightSet::new(MAX_LIGHTS);
+        lights.ambient = AmbientLight{
+            color: vector!(1.0, 1.0, 1.0),
+            intensity: 0.1,
+        };
+        lights.add(DirectionalLight{
+            color: vector!(0.545, 0.329, 0.929),
+            intensity: 0.66,
+            direction: vector!(1.0, 1.0, 1.0).normalize(),
+            shadow: None,
+        }).unwrap();
+
+        TestApplication {
             render,
             mesh: Mesh::new(),
             computed_mesh: Mesh::new(),
             outline_mesh: Mesh::new(),
-            program: None,
-            outline_program: None,
+            material: None,
+            outline_material: None,
+            post: None,
             time: 0.0,
-            view: Matrix4::identity(),
-            projection: Matrix4::identity(),
-            ambient_light: AmbientLight{
-                color: vector!(1.0, 1.0, 1.0),
-                intensity: 0.1,
-            },
-            dir_light: DirectionalLight{
-                color: vector!(0.545, 0.329, 0.929),
-                intensity: 0.66,
-                direction: vector!(1.0, 1.0, 1.0).normalize(),
-            },
+            camera: Camera::perspective(70.0, 0.01, 100.0),
+            lights,
             perlin: Perlin::new(3),
-        })
+            shaders: HashMap::new(),
+        }
     }
 }
 
@@ -200,3 +315,23 @@ fn subdivide_trig(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vec<Vect
     let ca = ((c - a) * 0.5) + a;
     vec!(a, ab, ca, b, bc, ab, c, ca, bc, ab, bc, ca)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::soft::SoftRenderer;
+
+    #[test]
+    fn runs_headless() {
+        let mut app = TestApplication::with_renderer(SoftRenderer::new(32, 32));
+        app.start().unwrap();
+        app.update(0.1, &InputState::new());
+        app.render();
+
+        // The mesh fills the screen, and its outline is drawn in pink on top
+        let pixels = app.get_renderer().read_pixels(0, 0, 32, 32).unwrap();
+        assert_eq!(pixels.len(), 32 * 32 * 4);
+        assert!(pixels.chunks(4).all(|pixel| pixel[3] == 255 && pixel[..3] != [0, 0, 0]));
+        assert!(pixels.chunks(4).any(|pixel| pixel[0] > 200 && pixel[1] < 150));
+    }
+}
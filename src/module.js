@@ -4,6 +4,59 @@ await init();
 let client = new WebClient();
 client.start();
 
+const canvas = document.getElementById("canvas");
+// Fingers are reported through the touch events below, which also carry multi-touch
+const isTouch = (e) => e.pointerType === 'touch';
+canvas.addEventListener('pointermove', (e) => isTouch(e) || client.pointer_move(e));
+canvas.addEventListener('pointerdown', (e) => {
+    if (isTouch(e)) return;
+    canvas.setPointerCapture(e.pointerId);
+    client.pointer_down(e);
+});
+canvas.addEventListener('pointerup', (e) => isTouch(e) || client.pointer_up(e));
+canvas.addEventListener('wheel', (e) => {
+    e.preventDefault();
+    client.wheel(e);
+}, { passive: false });
+canvas.addEventListener('touchstart', (e) => client.touch_start(e));
+canvas.addEventListener('touchmove', (e) => client.touch_move(e));
+canvas.addEventListener('touchend', (e) => client.touch_end(e));
+canvas.addEventListener('touchcancel', (e) => client.touch_end(e));
+window.addEventListener('keydown', (e) => client.key_down(e));
+window.addEventListener('keyup', (e) => client.key_up(e));
+window.addEventListener('blur', () => client.blur());
+
+if (import.meta.hot) {
+    // Latest source of every shader, as only the stage that changed is sent
+    const sources = Object.fromEntries(Object.entries(
+        import.meta.glob('../@rsw/gl-test/src/shader/*.glsl', { query: '?raw', import: 'default', eager: true })
+    ).map(([file, source]) => [file.split('/').pop(), source]));
+
+    // The shaders TestApplication can reload and the files they are built from
+    const shaders = {
+        simple: ['simple.v.glsl', 'simple.f.glsl'],
+        flatcolor: ['simple.v.glsl', 'flatcolor.f.glsl'],
+    };
+
+    import.meta.hot.on('shader-update', ({ file, chunk, source }) => {
+        try {
+            if (chunk) {
+                client.reload_shader_chunk(file.replace(/\.glsl$/, ''), source);
+                return;
+            }
+
+            sources[file] = source;
+            for (const [name, [vert, frag]] of Object.entries(shaders)) {
+                if (file === vert || file === frag) {
+                    client.reload_shader(name, sources[vert], sources[frag]);
+                }
+            }
+        } catch (e) {
+            console.error(e);
+        }
+    });
+}
+
 let time = Date.now();
 function render() {
     const dt = Date.now() - time;
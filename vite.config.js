@@ -1,9 +1,36 @@
+import path from 'node:path';
 import { defineConfig } from 'vite';
 import { ViteRsw } from 'vite-plugin-rsw';
 
+// Hand edited shaders straight to the page rather than waiting on a wasm rebuild.
+// Chunks are anything under a `chunk` directory, see `WebClient::reload_shader_chunk`.
+function ShaderReload() {
+  return {
+    name: 'shader-reload',
+    apply: 'serve',
+    async handleHotUpdate({ file, server, read }) {
+      if (!file.endsWith('.glsl')) {
+        return;
+      }
+
+      server.ws.send({
+        type: 'custom',
+        event: 'shader-update',
+        data: {
+          file: path.basename(file),
+          chunk: path.basename(path.dirname(file)) === 'chunk',
+          source: await read(),
+        },
+      });
+      return [];
+    },
+  };
+}
+
 export default defineConfig({
   plugins: [
     ViteRsw(),
+    ShaderReload(),
   ],
   esbuild: {
     supported: {